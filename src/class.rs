@@ -0,0 +1,108 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use unicode_general_category::{get_general_category, GeneralCategory};
+
+// Unicode character classes for `Rule::char_class`. They're displayed like the `\p{..}` classes of 
+// regular expressions, general categories by their abbreviation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharClass {
+    Alphabetic,
+    Alphanumeric,
+    Category(GeneralCategory),
+    Lowercase,
+    Numeric,
+    Uppercase,
+    Whitespace,
+    XidContinue,
+    XidStart,
+}
+
+impl CharClass {
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Alphabetic => c.is_alphabetic(),
+            CharClass::Alphanumeric => c.is_alphanumeric(),
+            CharClass::Category(category) => get_general_category(c) == category,
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Numeric => c.is_numeric(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::XidContinue => unicode_ident::is_xid_continue(c),
+            CharClass::XidStart => unicode_ident::is_xid_start(c),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            CharClass::Alphabetic => "Alphabetic",
+            CharClass::Alphanumeric => "Alphanumeric",
+            CharClass::Category(category) => category.abbreviation(),
+            CharClass::Lowercase => "Lowercase",
+            CharClass::Numeric => "Numeric",
+            CharClass::Uppercase => "Uppercase",
+            CharClass::Whitespace => "White_Space",
+            CharClass::XidContinue => "XID_Continue",
+            CharClass::XidStart => "XID_Start",
+        };
+
+        write!(f, "\\p{{{}}}", name)
+    }
+}
+
+// A set of characters, kept as sorted ranges which don't overlap so a character is looked up with a 
+// binary search.
+#[derive(Clone)]
+pub(crate) struct CharSet {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    pub(crate) fn new(negated: bool, mut ranges: Vec<(char, char)>) -> Self {
+        ranges.sort();
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+
+        for (min, max) in ranges {
+            match merged.last_mut() {
+                Some(last) if min as u32 <= last.1 as u32 + 1 => last.1 = last.1.max(max),
+                _ => merged.push((min, max)),
+            }
+        }
+
+        Self { negated, ranges: merged }
+    }
+
+    pub(crate) fn contains(&self, c: char) -> bool {
+        let i = self.ranges.partition_point(|r| r.1 < c);
+        (i < self.ranges.len() && self.ranges[i].0 <= c) != self.negated
+    }
+}
+
+impl fmt::Display for CharSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}", if self.negated { "^" } else { "" })?;
+
+        for &(min, max) in &self.ranges {
+            if min == max {
+                write!(f, "{}", class_char(min))?;
+            }
+            else {
+                write!(f, "{}-{}", class_char(min), class_char(max))?;
+            }
+        }
+
+        write!(f, "]")
+    }
+}
+
+pub(crate) fn class_char(c: char) -> String {
+    match c {
+        '\\' | ']' | '^' | '-' => format!("\\{}", c),
+        c => c.escape_debug().to_string(),
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// A rule is displayed in the syntax of `Grammar`. Named rules are referred to by name and are listed
+// after the rule itself, unnamed rules are written out in place. Unnamed rules which refer back to 
+// themselves are written as `...`.
+
+use std::fmt;
+use super::{class_char, Assoc, Element, Fixity, Instr, Rule};
+
+impl<T, I: Element> fmt::Display for Rule<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut writer = Writer { named: vec![self.clone()], stack: Vec::new() };
+        let mut i = 0;
+
+        while i < writer.named.len() {
+            let rule = writer.named[i].clone();
+
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            if let Some(ref name) = rule.0.borrow().name {
+                write!(f, "{} = ", name)?;
+            }
+
+            let body = writer.body(&rule);
+            write!(f, "{}", body)?;
+            i += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, I: Element> fmt::Debug for Rule<T, I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+struct Writer<T, I: Element> {
+    named: Vec<Rule<T, I>>,
+    stack: Vec<usize>,
+}
+
+impl<T, I: Element> Writer<T, I> {
+    // The instructions of a rule as a sequence, or as alternatives when it's a single `any_of`.
+    fn body(&mut self, rule: &Rule<T, I>) -> String {
+        self.stack.push(rule.id());
+        let r = rule.0.borrow();
+
+        let body = match r.instr[..] {
+            [] => String::from("()"),
+            [Instr::AnyOf(ref rules)] => {
+                let alts: Vec<String> = rules.iter().map(|r| self.seq(r)).collect();
+                alts.join(" | ")
+            },
+            [Instr::Alter(ref list)] => list.iter().map(|a| format!("{:?}", a.0)).collect::<Vec<_>>().join(" | "),
+            [Instr::AlterCi(ref list)] => list.iter().map(|a| format!("{:?}i", a.0)).collect::<Vec<_>>().join(" | "),
+            [Instr::AlterString(ref list)] => list.iter().map(|a| format!("{:?}", a.0)).collect::<Vec<_>>().join(" | "),
+            ref instr => instr.iter().map(|i| self.instr(i)).collect::<Vec<_>>().join(" "),
+        };
+
+        self.stack.pop();
+        body
+    }
+
+    // A rule as an alternative of `any_of`, which doesn't need to be grouped unless it has alternatives
+    // of its own.
+    fn seq(&mut self, rule: &Rule<T, I>) -> String {
+        if rule.0.borrow().name.is_some() || self.stack.contains(&rule.id()) {
+            return self.reference(rule);
+        }
+
+        match rule.0.borrow().instr[..] {
+            [Instr::AnyOf(_)] | [Instr::Alter(_)] | [Instr::AlterCi(_)] | [Instr::AlterString(_)] => (),
+            _ => return self.body(rule),
+        }
+
+        format!("({})", self.body(rule))
+    }
+
+    // A rule as a single term, so it's grouped when it's written out in place.
+    fn atom(&mut self, rule: &Rule<T, I>) -> String {
+        if rule.0.borrow().name.is_some() || self.stack.contains(&rule.id()) {
+            return self.reference(rule);
+        }
+
+        match rule.0.borrow().instr[..] {
+            [Instr::Range(1, 1, _)] => (),
+            [ref i] if !matches!(i, Instr::AnyOf(_) | Instr::Alter(_) | Instr::AlterCi(_) | Instr::AlterString(_) | Instr::And(_) | Instr::Not(_) | Instr::Range(..)) => (),
+            _ => return format!("({})", self.body(rule)),
+        }
+
+        self.body(rule)
+    }
+
+    fn reference(&mut self, rule: &Rule<T, I>) -> String {
+        match rule.0.borrow().name {
+            Some(ref name) => {
+                if !self.named.iter().any(|r| r.id() == rule.id()) {
+                    self.named.push(rule.clone());
+                }
+
+                name.clone()
+            },
+            None => String::from("..."),
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr<Rule<T, I>, I>) -> String {
+        match *instr {
+            Instr::AnyChar => String::from("."),
+            Instr::AnyCharExcept(ref exclude) => format!("[^{}]", exclude.iter().map(|&c| class_char(c)).collect::<String>()),
+            Instr::Alter(ref list) if list.len() == 1 => format!("{:?}", list[0].0),
+            Instr::Alter(ref list) => format!("({})", list.iter().map(|a| format!("{:?}", a.0)).collect::<Vec<_>>().join(" | ")),
+            Instr::AlterCi(ref list) if list.len() == 1 => format!("{:?}i", list[0].0),
+            Instr::AlterCi(ref list) => format!("({})", list.iter().map(|a| format!("{:?}i", a.0)).collect::<Vec<_>>().join(" | ")),
+            Instr::AlterString(ref list) if list.len() == 1 => format!("{:?}", list[0].0),
+            Instr::AlterString(ref list) => format!("({})", list.iter().map(|a| format!("{:?}", a.0)).collect::<Vec<_>>().join(" | ")),
+            Instr::And(ref rule) => format!("&{}", self.atom(rule)),
+            Instr::AnyOf(ref rules) => format!("({})", rules.iter().map(|r| self.seq(r)).collect::<Vec<_>>().join(" | ")),
+            Instr::CharClass(class) => class.to_string(),
+            Instr::CharIf(_) => String::from("char_if(..)"),
+            Instr::CharIn(min, max) if min == max => format!("[{}]", class_char(min)),
+            Instr::CharSet(ref set) => set.to_string(),
+            Instr::CharIn(min, max) => format!("[{}-{}]", class_char(min), class_char(max)),
+            Instr::Eof => String::from("$"),
+            Instr::Guard(_) => String::from("guard(..)"),
+            Instr::Literal(text) => format!("{:?}", text),
+            Instr::LiteralCi(ref text) => format!("{:?}i", text),
+            Instr::LiteralString(ref text) => format!("{:?}", text),
+            Instr::NoBacktrack(_) => String::from("~"),
+            Instr::Not(ref rule) => format!("!{}", self.atom(rule)),
+            Instr::Precedence(ref atom, ref ops) => {
+                let mut table = vec![self.atom(atom)];
+
+                for op in ops {
+                    let op_atom = self.atom(&op.op);
+
+                    table.push(match op.fixity {
+                        Fixity::Infix(Assoc::Left) => format!("infix {} {} left", op_atom, op.power),
+                        Fixity::Infix(Assoc::Right) => format!("infix {} {} right", op_atom, op.power),
+                        Fixity::Postfix => format!("postfix {} {}", op_atom, op.power),
+                        Fixity::Prefix => format!("prefix {} {}", op_atom, op.power),
+                    });
+                }
+
+                format!("precedence({})", table.join(", "))
+            },
+            Instr::Range(min, max, ref rule) => format!("{}{}", self.atom(rule), quantifier(min, max)),
+            Instr::SepBy(min, max, ref item, ref sep, options) => {
+                let trailing = if options.trailing { ", trailing" } else { "" };
+                format!("sep_by({}, {}{}){}", self.atom(item), self.atom(sep), trailing, quantifier(min, max))
+            },
+            Instr::Token(ref kind) => format!("token({:?})", kind),
+            Instr::TokenIf(_) => String::from("token_if(..)"),
+        }
+    }
+}
+
+fn quantifier(min: u64, max: u64) -> String {
+    match (min, max) {
+        (1, 1) => String::new(),
+        (0, 1) => String::from("?"),
+        (0, u64::MAX) => String::from("*"),
+        (1, u64::MAX) => String::from("+"),
+        (min, u64::MAX) => format!("{{{},}}", min),
+        (0, max) => format!("{{,{}}}", max),
+        (min, max) if min == max => format!("{{{}}}", min),
+        (min, max) => format!("{{{},{}}}", min, max),
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// Rules scan the `char`s of a `&str` by default. A `Rule<T, I>` scans a slice of `I` instead, where `I`
+// are the tokens out of a lexer. Character leaves like `literal` only exist for `char` rules, tokens are
+// matched with `token` and `token_if`.
+
+use std::fmt;
+use std::iter::Cloned;
+use std::ops::Range;
+use std::slice::Iter;
+use std::str::Chars;
+
+pub trait Element: Clone {
+    type Iter<'s>: Iterator<Item = Self> + Clone where Self: 's;
+    type Kind: Clone + fmt::Debug + PartialEq;
+
+    fn as_char(&self) -> Option<char>;
+    fn kind(&self) -> Self::Kind;
+
+    // The byte offset in the source of an iterator over the source's chars, tokens have none.
+    fn offset(source: &str, iter: &Self::Iter<'_>) -> Option<usize>;
+    fn push_lexeme(&self, source: &str, lexeme: &mut String);
+}
+
+// A token refers to its text in the source by a byte range. That text is what ends up in the lexemes,
+// and errors are reported at the line and column where the token starts.
+pub trait Token: Clone {
+    type Kind: Clone + fmt::Debug + PartialEq;
+
+    fn kind(&self) -> Self::Kind;
+    fn span(&self) -> Range<usize>;
+}
+
+impl Element for char {
+    type Iter<'s> = Chars<'s>;
+    type Kind = char;
+
+    fn as_char(&self) -> Option<char> {
+        Some(*self)
+    }
+
+    fn kind(&self) -> char {
+        *self
+    }
+
+    fn offset(source: &str, iter: &Chars<'_>) -> Option<usize> {
+        Some(source.len() - iter.as_str().len())
+    }
+
+    fn push_lexeme(&self, _: &str, lexeme: &mut String) {
+        lexeme.push(*self);
+    }
+}
+
+impl<K: Token> Element for K {
+    type Iter<'s> = Cloned<Iter<'s, K>> where K: 's;
+    type Kind = K::Kind;
+
+    fn as_char(&self) -> Option<char> {
+        None
+    }
+
+    fn kind(&self) -> K::Kind {
+        Token::kind(self)
+    }
+
+    fn offset(_: &str, _: &Cloned<Iter<'_, K>>) -> Option<usize> {
+        None
+    }
+
+    fn push_lexeme(&self, source: &str, lexeme: &mut String) {
+        lexeme.push_str(&source[self.span()]);
+    }
+}
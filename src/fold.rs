@@ -0,0 +1,68 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// Simple case folding, the C and S mappings of the Unicode `CaseFolding.txt`. These map a character
+// to its simple lowercase, except for the characters in the table below. Characters without a C or S
+// mapping, like 'ß' or 'İ' which only have a full (F) or Turkic (T) one, fold to themselves.
+
+use std::cmp::Ordering;
+
+// The C and S mappings which aren't the simple lowercase, as (first, last, first folded) ranges sorted
+// by the first character. Cherokee folds to uppercase, so the uppercase letters fold to themselves.
+const FOLD: &[(char, char, char)] = &[
+    ('\u{00B5}', '\u{00B5}', '\u{03BC}'),
+    ('\u{0130}', '\u{0130}', '\u{0130}'),
+    ('\u{017F}', '\u{017F}', '\u{0073}'),
+    ('\u{0345}', '\u{0345}', '\u{03B9}'),
+    ('\u{03C2}', '\u{03C2}', '\u{03C3}'),
+    ('\u{03D0}', '\u{03D0}', '\u{03B2}'),
+    ('\u{03D1}', '\u{03D1}', '\u{03B8}'),
+    ('\u{03D5}', '\u{03D5}', '\u{03C6}'),
+    ('\u{03D6}', '\u{03D6}', '\u{03C0}'),
+    ('\u{03F0}', '\u{03F0}', '\u{03BA}'),
+    ('\u{03F1}', '\u{03F1}', '\u{03C1}'),
+    ('\u{03F5}', '\u{03F5}', '\u{03B5}'),
+    ('\u{13A0}', '\u{13F5}', '\u{13A0}'),
+    ('\u{13F8}', '\u{13FD}', '\u{13F0}'),
+    ('\u{1C80}', '\u{1C80}', '\u{0432}'),
+    ('\u{1C81}', '\u{1C81}', '\u{0434}'),
+    ('\u{1C82}', '\u{1C82}', '\u{043E}'),
+    ('\u{1C83}', '\u{1C83}', '\u{0441}'),
+    ('\u{1C84}', '\u{1C85}', '\u{0442}'),
+    ('\u{1C86}', '\u{1C86}', '\u{044A}'),
+    ('\u{1C87}', '\u{1C87}', '\u{0463}'),
+    ('\u{1C88}', '\u{1C88}', '\u{A64B}'),
+    ('\u{1E9B}', '\u{1E9B}', '\u{1E61}'),
+    ('\u{1FBE}', '\u{1FBE}', '\u{03B9}'),
+    ('\u{1FD3}', '\u{1FD3}', '\u{0390}'),
+    ('\u{1FE3}', '\u{1FE3}', '\u{03B0}'),
+    ('\u{AB70}', '\u{ABBF}', '\u{13A0}'),
+    ('\u{FB05}', '\u{FB05}', '\u{FB06}'),
+];
+
+pub(crate) fn fold_case(c: char) -> char {
+    if let Ok(idx) = FOLD.binary_search_by(|&(first, last, _)| {
+        if last < c {
+            Ordering::Less
+        }
+        else if first > c {
+            Ordering::Greater
+        }
+        else {
+            Ordering::Equal
+        }
+    }) {
+        let (first, _, folded) = FOLD[idx];
+        return char::from_u32(folded as u32 + (c as u32 - first as u32)).unwrap_or(c);
+    }
+
+    // The simple lowercase is the full lowercase when that's a single character, only 'İ' has a longer
+    // one and it's in the table.
+    let mut lower = c.to_lowercase();
+
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::collections::HashMap;
+use super::{scan, scan_prefix, scan_recover, scan_tokens, Branch, BranchRef, ScanObserver, CloneFn, Element, GrammarError, Graph, Guard, GuardRef, Instr, Mode, Rule, RuleBody, RuleError, SyncBranch, SyncGuardFn, Token};
+
+// An immutable copy of a rule graph which can be shared between threads. The rules refer to each other
+// by index, the root rule is at index 0.
+pub struct FrozenRule<T, I: Element = char> {
+    rules: Vec<FrozenBody<T, I>>,
+}
+
+pub(crate) struct FrozenBody<T, I: Element> {
+    branch: Option<SyncBranch<T>>,
+    guards: Vec<SyncGuardFn<T>>,
+    instr: Vec<Instr<usize, I>>,
+    memo: Option<CloneFn<T>>,
+    mode: Mode<usize>,
+    name: Option<String>,
+    recover: Option<usize>,
+}
+
+impl<T, I: Element> FrozenRule<T, I> {
+    pub(crate) fn new(root: &Rule<T, I>) -> Result<Self, GrammarError> {
+        let mut ids = HashMap::new();
+        let mut queue = vec![root.clone()];
+        let mut rules = Vec::new();
+        ids.insert(root.id(), 0);
+
+        while rules.len() < queue.len() {
+            let rule = queue[rules.len()].clone();
+            let r = rule.0.borrow();
+
+            let branch = match r.branch {
+                Some(Branch::Lexeme(_)) => return Err(GrammarError::Invalid(String::from("Branch function is not thread safe, use `Rule::new_sync` instead."))),
+                Some(Branch::Span(_)) => return Err(GrammarError::Invalid(String::from("Branch function is not thread safe, use `Rule::with_span_sync` instead."))),
+                Some(Branch::State(_)) => return Err(GrammarError::Invalid(String::from("Rules with a state can't be frozen."))),
+                Some(Branch::Sync(ref f)) => Some(f.clone()),
+                None => None,
+            };
+
+            let mut guards = Vec::new();
+
+            for guard in &r.guards {
+                match *guard {
+                    Guard::Lexeme(_) => return Err(GrammarError::Invalid(String::from("Guard is not thread safe, use `Rule::guard_sync` instead."))),
+                    Guard::State(_) => return Err(GrammarError::Invalid(String::from("Rules with a state can't be frozen."))),
+                    Guard::Sync(ref f) => guards.push(f.clone()),
+                }
+            }
+
+            let mut index = |rule: &Rule<T, I>| *ids.entry(rule.id()).or_insert_with(|| {
+                queue.push(rule.clone());
+                queue.len() - 1
+            });
+
+            let instr = r.instr.iter().map(|i| i.map(&mut index)).collect();
+            let recover = r.recover.as_ref().map(&mut index);
+
+            let mode = match r.mode {
+                Mode::Inherit => Mode::Inherit,
+                Mode::Lexical => Mode::Lexical,
+                Mode::Syntactic(ref trivia) => Mode::Syntactic(index(trivia)),
+            };
+
+            rules.push(FrozenBody {
+                branch,
+                guards,
+                instr,
+                memo: r.memo,
+                mode,
+                name: r.name.clone(),
+                recover,
+            });
+        }
+
+        Ok(Self { rules })
+    }
+}
+
+impl<T> FrozenRule<T> {
+    pub fn scan(&self, code: &str) -> Result<Vec<T>, RuleError> {
+        scan(self, &0, code, None)
+    }
+
+    pub fn scan_prefix(&self, code: &str) -> Result<(Vec<T>, usize, usize), RuleError> {
+        scan_prefix(self, &0, code)
+    }
+
+    pub fn scan_recover(&self, code: &str) -> (Option<Vec<T>>, Vec<RuleError>) {
+        scan_recover(self, &0, code)
+    }
+
+    pub fn scan_traced(&self, code: &str, observer: &mut impl ScanObserver) -> Result<Vec<T>, RuleError> {
+        scan(self, &0, code, Some(observer))
+    }
+
+    pub fn try_scan(&self, code: &str) -> Result<Vec<T>, GrammarError> {
+        if let Some(rule) = self.rules.iter().find(|r| r.instr.is_empty()) {
+            return Err(GrammarError::Undefined(rule.name.clone()));
+        }
+
+        self.scan(code).map_err(GrammarError::Scan)
+    }
+}
+
+impl<T, I: Token> FrozenRule<T, I> {
+    pub fn scan_tokens(&self, tokens: &[I], source: &str) -> Result<Vec<T>, RuleError> {
+        scan_tokens(self, &0, tokens, source)
+    }
+}
+
+impl<T, I: Element> Graph<T, I> for FrozenRule<T, I> {
+    type Node = usize;
+    type Rule = FrozenBody<T, I>;
+    type Body<'a> = &'a FrozenBody<T, I> where Self: 'a;
+
+    fn body<'a>(&'a self, node: &'a usize) -> &'a FrozenBody<T, I> {
+        &self.rules[*node]
+    }
+
+    fn id(&self, node: &usize) -> usize {
+        *node
+    }
+}
+
+impl<T, I: Element> RuleBody<T, usize, I> for FrozenBody<T, I> {
+    fn branch_fn(&self) -> Option<BranchRef<'_, T>> {
+        self.branch.as_ref().map(SyncBranch::branch_ref)
+    }
+
+    fn guard(&self, idx: usize) -> GuardRef<'_, T> {
+        GuardRef::Lexeme(self.guards[idx].as_ref())
+    }
+
+    fn instr(&self) -> &[Instr<usize, I>] {
+        &self.instr
+    }
+
+    fn memo(&self) -> Option<CloneFn<T>> {
+        self.memo
+    }
+
+    fn mode(&self) -> &Mode<usize> {
+        &self.mode
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn recover(&self) -> Option<&usize> {
+        self.recover.as_ref()
+    }
+}
@@ -0,0 +1,601 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// Grammar expressions are compiled into a `Rule<T>` graph. The syntax is:
+//
+//   "abc" 'abc'     literal, with the escapes \\ \" \' \n \r \t and \u{..}
+//   "abc"i          case insensitive literal
+//   [a-z_]          one character out of the ranges and characters, [^...] negates
+//   .               any character
+//   $               end of file
+//   <name> name     reference to another rule of the grammar
+//   ( ... )         group
+//   a | b           a or b, the first alternative that matches wins
+//   a? a* a+        maybe, none or many, at least one
+//   a{n} a{m,n}     exact, between, a{m,} and a{,n} leave out a bound
+//   &a              and, a must match but no input is consumed
+//   !a              not, a must not match and no input is consumed
+//   ~               no backtrack, failing after this point is a syntax error
+//
+// After `set_trivia` the rules skip the trivia expression between their elements, except for the rules
+// marked with `set_lexical` and everything they scan.
+
+use std::collections::{HashMap, HashSet};
+use std::iter::Peekable;
+use std::str::Chars;
+use super::{or_panic, Branch, BranchFn, Rule, RuleError, RuleErrorKind, SyncBranch, SyncBranchFn};
+
+pub struct Grammar<T> {
+    defined: HashSet<String>,
+    lexical: HashSet<String>,
+    refs: Vec<Reference>,
+    rules: HashMap<String, Rule<T>>,
+    trivia: Option<Rule<T>>,
+}
+
+// A reference to the rule `name`, at `idx` in the expression of the rule `from` or of the trivia.
+struct Reference {
+    expr: String,
+    from: Option<String>,
+    idx: usize,
+    name: String,
+}
+
+enum Node {
+    And(Box<Node>),
+    AnyChar,
+    Class(bool, Vec<(char, char)>),
+    Eof,
+    Group(Vec<Vec<Node>>),
+    Literal(String, bool),
+    NoBacktrack,
+    Not(Box<Node>),
+    Range(u64, u64, Box<Node>),
+    Ref(String, usize),
+}
+
+impl<T> Default for Grammar<T> {
+    fn default() -> Self {
+        Self {
+            defined: HashSet::new(),
+            lexical: HashSet::new(),
+            refs: Vec::new(),
+            rules: HashMap::new(),
+            trivia: None,
+        }
+    }
+}
+
+impl<T> Grammar<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, expr: &str, branch_fn: Option<BranchFn<T>>) -> Result<(), RuleError> {
+        let rule = self.define(name, expr)?;
+        rule.0.borrow_mut().branch = branch_fn.map(Branch::Lexeme);
+        Ok(())
+    }
+
+    pub fn add_sync(&mut self, name: &str, expr: &str, branch_fn: Option<SyncBranchFn<T>>) -> Result<(), RuleError> {
+        let rule = self.define(name, expr)?;
+        rule.0.borrow_mut().branch = branch_fn.map(|f| Branch::Sync(SyncBranch::Lexeme(f)));
+        Ok(())
+    }
+
+    pub fn rule(&self, name: &str) -> Result<Rule<T>, RuleError> {
+        if !self.defined.contains(name) {
+            return Err(RuleError { kind: RuleErrorKind::UndefinedRule, ..RuleError::new("", 0, format!("Rule \"{}\" is not defined.", name)) });
+        }
+
+        if let Some(r) = self.undefined_ref(name) {
+            let msg = match r.from {
+                Some(ref from) => format!("Rule \"{}\" is not defined, it's referred to by rule \"{}\".", r.name, from),
+                None => format!("Rule \"{}\" is not defined, it's referred to by the trivia.", r.name),
+            };
+
+            return Err(RuleError { kind: RuleErrorKind::UndefinedRule, ..RuleError::new(&r.expr, r.idx, msg) });
+        }
+
+        Ok(self.rules[name].clone())
+    }
+
+    pub fn scan(&self, root: &str, code: &str) -> Result<Vec<T>, RuleError> {
+        self.rule(root)?.scan(code)
+    }
+
+    pub fn set_lexical(&mut self, name: &str) -> Result<(), RuleError> {
+        if !is_name(name) {
+            return Err(RuleError::new("", 0, format!("Invalid rule name \"{}\".", name)));
+        }
+
+        self.lexical.insert(name.to_string());
+        self.get_or_insert(name).lexical();
+        Ok(())
+    }
+
+    pub fn set_trivia(&mut self, expr: &str) -> Result<(), RuleError> {
+        let alts = Parser::new(expr).parse()?;
+        let trivia = Rule::default();
+        self.compile_alts(&trivia, alts, expr);
+
+        for (name, rule) in &self.rules {
+            if !self.lexical.contains(name) {
+                rule.trivia(&trivia);
+            }
+        }
+
+        self.trivia = Some(trivia);
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, expr: &str) -> Result<Rule<T>, RuleError> {
+        if !is_name(name) {
+            return Err(RuleError::new(expr, 0, format!("Invalid rule name \"{}\".", name)));
+        }
+
+        if self.defined.contains(name) {
+            return Err(RuleError::new(expr, 0, format!("Rule \"{}\" is already defined.", name)));
+        }
+
+        let alts = Parser::new(expr).parse()?;
+        let rule = self.get_or_insert(name);
+        let refs = self.refs.len();
+        self.compile_alts(&rule, alts, expr);
+        self.defined.insert(name.to_string());
+
+        for r in &mut self.refs[refs..] {
+            r.from = Some(name.to_string());
+        }
+
+        Ok(rule)
+    }
+
+    // The first reference to a rule which isn't defined, in the rules the root scans. Every rule but a
+    // lexical one scans the trivia, which is `None` here.
+    fn undefined_ref(&self, root: &str) -> Option<&Reference> {
+        let mut reached = vec![Some(root)];
+        let mut i = 0;
+
+        while i < reached.len() {
+            let from = reached[i];
+
+            for r in self.refs.iter().filter(|r| r.from.as_deref() == from) {
+                if !self.defined.contains(&r.name) {
+                    return Some(r);
+                }
+
+                if !reached.contains(&Some(r.name.as_str())) {
+                    reached.push(Some(&r.name));
+                }
+            }
+
+            if self.trivia.is_some() && from.is_some_and(|name| !self.lexical.contains(name)) && !reached.contains(&None) {
+                reached.push(None);
+            }
+
+            i += 1;
+        }
+
+        None
+    }
+
+    fn compile_alts(&mut self, rule: &Rule<T>, mut alts: Vec<Vec<Node>>, expr: &str) {
+        if alts.len() == 1 {
+            let seq = alts.pop().unwrap();
+            self.compile_seq(rule, seq, expr);
+        }
+        else {
+            let rules: Vec<Rule<T>> = alts.into_iter().map(|seq| {
+                let r = Rule::default();
+                self.compile_seq(&r, seq, expr);
+                r
+            }).collect();
+
+            rule.any_of(rules.iter().collect());
+        }
+    }
+
+    fn compile_seq(&mut self, rule: &Rule<T>, seq: Vec<Node>, expr: &str) {
+        for node in seq {
+            match node {
+                Node::AnyChar => { rule.any_char(); },
+                Node::Class(false, ref ranges) if ranges.len() == 1 => { rule.char_in(ranges[0].0, ranges[0].1); },
+                Node::Class(negate, ranges) => { or_panic(rule.push_char_set(negate, ranges)); },
+                Node::Eof => { rule.eof(); },
+                Node::Group(alts) if alts.len() == 1 => self.compile_seq(rule, alts.into_iter().next().unwrap(), expr),
+                Node::Literal(text, false) => { rule.literal_string(text); },
+                Node::Literal(text, true) => { rule.literal_string_ci(text); },
+                Node::NoBacktrack => { rule.no_backtrack(String::from("Syntax error.")); },
+                Node::And(node) => { rule.and(&self.compile_node(*node, expr)); },
+                Node::Not(node) => { rule.not(&self.compile_node(*node, expr)); },
+                Node::Range(min, max, node) => { rule.between(min, max, &self.compile_node(*node, expr)); },
+                node => { rule.one(&self.compile_node(node, expr)); },
+            }
+        }
+    }
+
+    fn compile_node(&mut self, node: Node, expr: &str) -> Rule<T> {
+        match node {
+            Node::Group(alts) => {
+                let r = Rule::default();
+                self.compile_alts(&r, alts, expr);
+                r
+            },
+            Node::Ref(name, idx) => {
+                self.refs.push(Reference { expr: expr.to_string(), from: None, idx, name: name.clone() });
+                self.get_or_insert(&name)
+            },
+            node => {
+                let r = Rule::default();
+                self.compile_seq(&r, vec![node], expr);
+                r
+            },
+        }
+    }
+
+    fn get_or_insert(&mut self, name: &str) -> Rule<T> {
+        self.rules.entry(name.to_string()).or_insert_with(|| {
+            let rule = Rule::default();
+            rule.set_name(name);
+
+            if let Some(ref trivia) = self.trivia {
+                rule.trivia(trivia);
+            }
+
+            rule
+        }).clone()
+    }
+}
+
+// Parses a bracket expression like `[^a-z\]]` into whether it's negated and its ranges.
+pub(crate) fn parse_char_set(expr: &str) -> Result<(bool, Vec<(char, char)>), RuleError> {
+    let mut parser = Parser::new(expr);
+
+    if parser.peek() != Some('[') {
+        return Err(parser.error("Expected \"[\"."));
+    }
+
+    let node = parser.parse_class()?;
+
+    if parser.iter.peek().is_some() {
+        return Err(parser.error("Unexpected input after \"]\"."));
+    }
+
+    match node {
+        Node::Class(negate, ranges) => Ok((negate, ranges)),
+        _ => unreachable!(),
+    }
+}
+
+struct Parser<'s> {
+    expr: &'s str,
+    index: usize,
+    iter: Peekable<Chars<'s>>,
+}
+
+impl<'s> Parser<'s> {
+    fn new(expr: &'s str) -> Self {
+        Self {
+            expr,
+            index: 0,
+            iter: expr.chars().peekable(),
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Vec<Node>>, RuleError> {
+        let alts = self.parse_alts()?;
+
+        match self.peek() {
+            None => Ok(alts),
+            Some(')') => Err(self.error("Unexpected \")\".")),
+            Some(_) => Err(self.error("Syntax error.")),
+        }
+    }
+
+    fn parse_alts(&mut self) -> Result<Vec<Vec<Node>>, RuleError> {
+        let mut alts = vec![self.parse_seq()?];
+
+        while self.eat('|') {
+            alts.push(self.parse_seq()?);
+        }
+
+        Ok(alts)
+    }
+
+    fn parse_seq(&mut self) -> Result<Vec<Node>, RuleError> {
+        let mut seq = Vec::new();
+
+        while let Some(node) = self.parse_item()? {
+            seq.push(node);
+        }
+
+        if seq.is_empty() {
+            Err(self.error("Expression expected."))
+        }
+        else {
+            Ok(seq)
+        }
+    }
+
+    fn parse_item(&mut self) -> Result<Option<Node>, RuleError> {
+        if self.eat('&') {
+            return match self.parse_item()? {
+                Some(node) => Ok(Some(Node::And(Box::new(node)))),
+                None => Err(self.error("Expression expected after \"&\".")),
+            };
+        }
+
+        if self.eat('!') {
+            return match self.parse_item()? {
+                Some(node) => Ok(Some(Node::Not(Box::new(node)))),
+                None => Err(self.error("Expression expected after \"!\".")),
+            };
+        }
+
+        if self.eat('~') {
+            return Ok(Some(Node::NoBacktrack));
+        }
+
+        let node = match self.parse_atom()? {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+
+        let (min, max) = match self.peek() {
+            Some('?') => { self.next(); (0, 1) },
+            Some('*') => { self.next(); (0, u64::MAX) },
+            Some('+') => { self.next(); (1, u64::MAX) },
+            Some('{') => { self.next(); self.parse_bounds()? },
+            _ => return Ok(Some(node)),
+        };
+
+        Ok(Some(Node::Range(min, max, Box::new(node))))
+    }
+
+    fn parse_atom(&mut self) -> Result<Option<Node>, RuleError> {
+        let idx = self.index;
+
+        let node = match self.peek() {
+            Some('"') | Some('\'') => {
+                let text = self.parse_literal()?;
+                Node::Literal(text, self.parse_case_insensitive())
+            },
+            Some('[') => self.parse_class()?,
+            Some('.') => { self.next(); Node::AnyChar },
+            Some('$') => { self.next(); Node::Eof },
+            Some('(') => {
+                self.next();
+                let alts = self.parse_alts()?;
+
+                if !self.eat(')') {
+                    return Err(self.error("Expected \")\"."));
+                }
+
+                Node::Group(alts)
+            },
+            Some('<') => {
+                self.next();
+                let name = self.parse_name();
+
+                if !is_name(&name) {
+                    return Err(self.error("Rule name expected."));
+                }
+
+                if self.iter.next_if_eq(&'>').is_none() {
+                    return Err(self.error("Expected \">\"."));
+                }
+
+                self.index += 1;
+                Node::Ref(name, idx)
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => Node::Ref(self.parse_name(), idx),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(node))
+    }
+
+    fn parse_bounds(&mut self) -> Result<(u64, u64), RuleError> {
+        let min = self.parse_number()?;
+
+        let bounds = if self.eat(',') {
+            (min.unwrap_or(0), self.parse_number()?.unwrap_or(u64::MAX))
+        }
+        else {
+            match min {
+                Some(n) => (n, n),
+                None => return Err(self.error("Number expected.")),
+            }
+        };
+
+        if !self.eat('}') {
+            return Err(self.error("Expected \"}\"."));
+        }
+
+        if bounds.0 > bounds.1 {
+            return Err(self.error("Minimum is greater than maximum."));
+        }
+
+        Ok(bounds)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RuleError> {
+        self.next();
+        let negate = self.iter.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+
+        if negate {
+            self.index += 1;
+        }
+
+        loop {
+            let min = match self.next() {
+                Some(']') if !ranges.is_empty() => break,
+                Some('\\') => self.parse_escape()?,
+                Some(c) => c,
+                None => return Err(self.error("Expected \"]\".")),
+            };
+
+            // A "-" right before the "]" is the character itself, like a "-" at the start.
+            let mut ahead = self.iter.clone();
+            let range = ahead.next() == Some('-') && ahead.next() != Some(']');
+
+            let max = if range {
+                self.next();
+
+                match self.next() {
+                    Some('\\') => self.parse_escape()?,
+                    Some(c) => c,
+                    None => return Err(self.error("Character expected after \"-\".")),
+                }
+            }
+            else {
+                min
+            };
+
+            if min > max {
+                return Err(self.error("Invalid character range."));
+            }
+
+            ranges.push((min, max));
+        }
+
+        Ok(Node::Class(negate, ranges))
+    }
+
+    fn parse_escape(&mut self) -> Result<char, RuleError> {
+        match self.next() {
+            Some('n') => Ok('\n'),
+            Some('r') => Ok('\r'),
+            Some('t') => Ok('\t'),
+            Some('u') => {
+                if self.next() != Some('{') {
+                    return Err(self.error("Expected \"{\"."));
+                }
+
+                let mut hex = String::new();
+
+                loop {
+                    match self.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_hexdigit() => hex.push(c),
+                        _ => return Err(self.error("Invalid unicode escape.")),
+                    }
+                }
+
+                u32::from_str_radix(&hex, 16).ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("Invalid unicode escape."))
+            },
+            Some(c) => Ok(c),
+            None => Err(self.error("Unexpected end of expression.")),
+        }
+    }
+
+    // An `i` right after a literal makes it case insensitive, unless it's the start of a name.
+    fn parse_case_insensitive(&mut self) -> bool {
+        let mut iter = self.iter.clone();
+
+        if iter.next() == Some('i') && !iter.next().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.next();
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<String, RuleError> {
+        let quote = self.next().unwrap();
+        let mut text = String::new();
+
+        loop {
+            match self.next() {
+                Some('\\') => text.push(self.parse_escape()?),
+                Some(c) if c == quote => break,
+                Some(c) => text.push(c),
+                None => return Err(self.error("Unterminated literal.")),
+            }
+        }
+
+        if text.is_empty() {
+            return Err(self.error("Literal text must at least 1 character long."));
+        }
+
+        Ok(text)
+    }
+
+    fn parse_name(&mut self) -> String {
+        let mut name = String::new();
+
+        while let Some(c) = self.iter.next_if(|&c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            name.push(c);
+            self.index += 1;
+        }
+
+        name
+    }
+
+    fn parse_number(&mut self) -> Result<Option<u64>, RuleError> {
+        self.skip_ws();
+        let mut digits = String::new();
+
+        while let Some(c) = self.iter.next_if(char::is_ascii_digit) {
+            digits.push(c);
+            self.index += 1;
+        }
+
+        if digits.is_empty() {
+            Ok(None)
+        }
+        else {
+            digits.parse().map(Some).map_err(|_| self.error("Number is too large."))
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.next();
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn error(&self, msg: &str) -> RuleError {
+        RuleError::new(self.expr, self.index, msg.to_string())
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.iter.next();
+
+        if c.is_some() {
+            self.index += 1;
+        }
+
+        c
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.iter.peek().cloned()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.iter.next_if(|c| c.is_whitespace()).is_some() {
+            self.index += 1;
+        }
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        _ => false,
+    }
+}
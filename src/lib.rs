@@ -2,36 +2,252 @@
 // Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
 // This file may not be copied, modified, or distributed except according to those terms.
 
-use std::cell::RefCell;
+use std::any::Any;
+use std::cell::{Cell, OnceCell, Ref, RefCell};
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, Range};
 use std::rc::Rc;
-use std::str::Chars;
+use std::sync::Arc;
 
-enum Progress<'s, T> {
-    Some { steps: usize, ctx: ScanCtx<'s, T> },
-    No(ScanCtx<'s, T>),
-    Error { idx: usize, msg: String },
+mod class;
+mod display;
+mod element;
+mod fold;
+mod frozen;
+mod grammar;
+mod precedence;
+mod search;
+mod state;
+mod trace;
+mod validate;
+
+pub use class::CharClass;
+pub use element::{Element, Token};
+use class::{class_char, CharSet};
+use fold::fold_case;
+pub use frozen::FrozenRule;
+pub use grammar::Grammar;
+pub use precedence::{Assoc, PrecedenceBuilder};
+use precedence::{Fixity, Operator};
+use state::State;
+pub use search::{Match, Matches, Split};
+pub use trace::{RuleInfo, ScanObserver, TracePrinter};
+pub use validate::GrammarIssue;
+pub use unicode_general_category::GeneralCategory;
+
+enum Progress<'s, T, I: Element + 's> {
+    Some { steps: usize, ctx: ScanCtx<'s, T, I> },
+    No(ScanCtx<'s, T, I>),
+    Error { idx: usize, kind: RuleErrorKind, msg: String },
+}
+
+pub struct Rule<T, I: Element = char>(Rc<RefCell<_Rule<T, I>>>);
+
+impl<T, I: Element> Clone for Rule<T, I> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+pub type BranchFn<T> = Box<dyn Fn(Vec<T>, &str) -> Result<T, String>>;
+pub type SyncBranchFn<T> = Arc<dyn Fn(Vec<T>, &str) -> Result<T, String> + Send + Sync>;
+
+type CloneFn<T> = fn(&[T]) -> Vec<T>;
+type GuardFn<T> = Box<DynGuardFn<'static, T>>;
+type DynBranchFn<'a, T> = dyn Fn(Vec<T>, &str) -> Result<T, String> + 'a;
+type DynGuardFn<'a, T> = dyn Fn(&str, &[T]) -> bool + 'a;
+type DynSpanFn<T> = dyn Fn(Vec<T>, &str, Span) -> Result<T, String>;
+type DynStateFn<T> = dyn Fn(&mut dyn Any, Vec<T>, &str) -> Result<T, String>;
+type DynStateGuardFn<T> = dyn Fn(&dyn Any, &str, &[T]) -> Result<bool, String>;
+type SpanFn<T> = Box<DynSpanFn<T>>;
+type StateFn<T> = Box<DynStateFn<T>>;
+type StateGuardFn<T> = Box<DynStateGuardFn<T>>;
+type SyncGuardFn<T> = Arc<dyn Fn(&str, &[T]) -> bool + Send + Sync>;
+type SyncSpanFn<T> = Arc<dyn Fn(Vec<T>, &str, Span) -> Result<T, String> + Send + Sync>;
+
+struct _Rule<T, I: Element> {
+    branch: Option<Branch<T>>,
+    guards: Vec<Guard<T>>,
+    instr: Vec<Instr<Rule<T, I>, I>>,
+    memo: Option<CloneFn<T>>,
+    mode: Mode<Rule<T, I>>,
+    name: Option<String>,
+    recover: Option<Rule<T, I>>,
+}
+
+// The branch function of a rule, the one it's made with. Only the sync ones can be frozen.
+enum Branch<T> {
+    Lexeme(BranchFn<T>),
+    Span(SpanFn<T>),
+    State(StateFn<T>),
+    Sync(SyncBranch<T>),
+}
+
+// A guard of a rule, made like the branch functions. Only the sync ones can be frozen.
+enum Guard<T> {
+    Lexeme(GuardFn<T>),
+    State(StateGuardFn<T>),
+    Sync(SyncGuardFn<T>),
 }
 
-pub struct Rule<T>(Rc<RefCell<_Rule<T>>>);
+enum SyncBranch<T> {
+    Lexeme(SyncBranchFn<T>),
+    Span(SyncSpanFn<T>),
+}
 
-impl<T> Clone for Rule<T> {
+impl<T> Clone for SyncBranch<T> {
     fn clone(&self) -> Self {
-        Self { 0: self.0.clone() }
+        match *self {
+            SyncBranch::Lexeme(ref f) => SyncBranch::Lexeme(f.clone()),
+            SyncBranch::Span(ref f) => SyncBranch::Span(f.clone()),
+        }
+    }
+}
+
+impl<T> SyncBranch<T> {
+    fn branch_ref(&self) -> BranchRef<'_, T> {
+        match *self {
+            SyncBranch::Lexeme(ref f) => BranchRef::Lexeme(f.as_ref()),
+            SyncBranch::Span(ref f) => BranchRef::Span(f.as_ref()),
+        }
+    }
+}
+
+// How `Rule::sep_by_with` treats separators. By default a separator after the last item doesn't match 
+// and the branches of the separators are dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SepOptions {
+    pub keep_sep: bool,
+    pub trailing: bool,
+}
+
+// The matched region of a rule, as passed to the branch functions of `Rule::with_span`. The line starts 
+// at 1 and the column at 0, like they do in `RuleError`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub col: usize,
+    pub end_byte: usize,
+    pub end_char: usize,
+    pub line: usize,
+    pub start_byte: usize,
+    pub start_char: usize,
+}
+
+enum BranchRef<'a, T> {
+    Lexeme(&'a DynBranchFn<'a, T>),
+    Span(&'a DynSpanFn<T>),
+    State(&'a DynStateFn<T>),
+}
+
+enum GuardRef<'a, T> {
+    Lexeme(&'a DynGuardFn<'a, T>),
+    State(&'a DynStateGuardFn<T>),
+}
+
+// The scanner reads the rules through a graph. That's either the `Rule<T>` nodes themselves or the 
+// rules of a `FrozenRule<T>`, which are referred to by index.
+trait Graph<T, I: Element> {
+    type Node: Clone;
+    type Rule: RuleBody<T, Self::Node, I>;
+    type Body<'a>: Deref<Target = Self::Rule> where Self: 'a;
+
+    fn body<'a>(&'a self, node: &'a Self::Node) -> Self::Body<'a>;
+    fn id(&self, node: &Self::Node) -> usize;
+}
+
+trait RuleBody<T, N, I: Element> {
+    fn branch_fn(&self) -> Option<BranchRef<'_, T>>;
+    fn guard(&self, idx: usize) -> GuardRef<'_, T>;
+    fn instr(&self) -> &[Instr<N, I>];
+    fn memo(&self) -> Option<CloneFn<T>>;
+    fn mode(&self) -> &Mode<N>;
+    fn name(&self) -> Option<&str>;
+    fn recover(&self) -> Option<&N>;
+}
+
+struct RuleGraph<T, I>(PhantomData<(T, I)>);
+
+impl<T, I: Element> Graph<T, I> for RuleGraph<T, I> {
+    type Node = Rule<T, I>;
+    type Rule = _Rule<T, I>;
+    type Body<'a> = Ref<'a, _Rule<T, I>> where Self: 'a;
+
+    fn body<'a>(&'a self, node: &'a Rule<T, I>) -> Ref<'a, _Rule<T, I>> {
+        node.0.borrow()
+    }
+
+    fn id(&self, node: &Rule<T, I>) -> usize {
+        node.id()
     }
 }
 
-struct _Rule<T> {
-    branch_fn: Option<Box<dyn Fn(Vec<T>, &str) -> Result<T, String>>>,
-    instr: Vec<Instr<T>>,
+impl<T, I: Element> RuleBody<T, Rule<T, I>, I> for _Rule<T, I> {
+    fn branch_fn(&self) -> Option<BranchRef<'_, T>> {
+        self.branch.as_ref().map(|branch| match *branch {
+            Branch::Lexeme(ref f) => BranchRef::Lexeme(f.as_ref()),
+            Branch::Span(ref f) => BranchRef::Span(f.as_ref()),
+            Branch::State(ref f) => BranchRef::State(f.as_ref()),
+            Branch::Sync(ref f) => f.branch_ref(),
+        })
+    }
+
+    fn guard(&self, idx: usize) -> GuardRef<'_, T> {
+        match self.guards[idx] {
+            Guard::Lexeme(ref f) => GuardRef::Lexeme(f.as_ref()),
+            Guard::State(ref f) => GuardRef::State(f.as_ref()),
+            Guard::Sync(ref f) => GuardRef::Lexeme(f.as_ref()),
+        }
+    }
+
+    fn instr(&self) -> &[Instr<Rule<T, I>, I>] {
+        &self.instr
+    }
+
+    fn memo(&self) -> Option<CloneFn<T>> {
+        self.memo
+    }
+
+    fn mode(&self) -> &Mode<Rule<T, I>> {
+        &self.mode
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn recover(&self) -> Option<&Rule<T, I>> {
+        self.recover.as_ref()
+    }
 }
 
+// Where an error is found. The end is the byte and char index after the char or token at the error, 
+// there's none at the end of the input. The error is returned by value, boxing it or its fields would 
+// change the signature of every scan method for an error which is only built once per scan.
 #[derive(Debug)]
 pub struct RuleError {
     pub col: usize,
+    pub end: Option<(usize, usize)>,
+    pub expected: Vec<String>,
+    pub kind: RuleErrorKind,
     pub line: usize,
     pub msg: String,
+    pub start_byte: usize,
+    pub start_char: usize,
+    pub token: Option<usize>,
+}
+
+// Why scanning failed. A committed error comes from a `no_backtrack` with its message as label, a semantic
+// error from a branch function. Trailing input is what's left when the root matched but not all of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleErrorKind {
+    Committed { label: String },
+    Semantic,
+    Syntax,
+    TrailingInput,
+    UndefinedRule,
 }
 
 impl fmt::Display for RuleError {
@@ -46,80 +262,307 @@ impl Error for RuleError {
     }
 }
 
+// An invalid argument to a builder method, or a rule which isn't defined when scanning with `try_scan`.
+#[derive(Debug)]
+pub enum GrammarError {
+    Invalid(String),
+    Scan(RuleError),
+    Undefined(Option<String>),
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrammarError::Invalid(ref msg) => write!(f, "{}", msg),
+            GrammarError::Scan(ref err) => write!(f, "{}", err),
+            GrammarError::Undefined(Some(ref name)) => write!(f, "Rule \"{}\" is not defined.", name),
+            GrammarError::Undefined(None) => write!(f, "Rule is not defined."),
+        }
+    }
+}
+
+impl Error for GrammarError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            GrammarError::Scan(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 impl RuleError {
     fn new(text: &str, index: usize, msg: String) -> Self {
+        // An `eof` leaf steps past the end of the input, an error after it is at the end.
         let char_count = text.char_indices().count();
+        let index = index.min(char_count);
 
         let chr_idx = if char_count == index { 
             text.len()
         } 
         else { 
-            text.char_indices().skip(index).next().map(|x| x.0).unwrap()
+            text.char_indices().nth(index).map(|x| x.0).unwrap()
         };
 
         let pos = cursor_pos(&text[..chr_idx]);
+        let end = text[chr_idx..].chars().next().map(|c| (chr_idx + c.len_utf8(), index + 1));
 
         Self { 
             col: pos.col,
+            end,
+            expected: Vec::new(),
+            kind: RuleErrorKind::Syntax,
             line: pos.line,
             msg, 
+            start_byte: chr_idx,
+            start_char: index,
+            token: None,
         }
     }
+
+    fn expected(text: &str, index: usize, expected: Vec<String>) -> Self {
+        let msg = match expected.split_last() {
+            Some((last, [])) => format!("Expected {}.", last),
+            Some((last, rest)) => format!("Expected {} or {}.", rest.join(", "), last),
+            None => String::from("Syntax error."),
+        };
+
+        Self { expected, ..Self::new(text, index, msg) }
+    }
+
+    // The line of the source with the error, underlined from the start to the end of the error or to the 
+    // end of the line. Tabs are kept in the indentation of the underline so it lines up.
+    pub fn render(&self, source: &str) -> String {
+        let start = self.start_byte.min(source.len());
+        let line_start = source[..start].rfind(['\r', '\n']).map_or(0, |i| i + 1);
+        let line_end = source[start..].find(['\r', '\n']).map_or(source.len(), |i| start + i);
+        let end = self.end.map_or(start, |(end, _)| end).clamp(start, line_end);
+
+        let indent: String = source[line_start..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let underline = "^".repeat(source[start..end].chars().count().max(1));
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+
+        format!("error: {}\n{}--> line {}, column {}\n{} |\n{} | {}\n{} | {}{}\n", 
+            self.msg, gutter, self.line, self.col, gutter, number, &source[line_start..line_end], gutter, indent, underline)
+    }
 }
 
-enum Instr<T> {
+enum Instr<N, I: Element = char> {
     AnyChar,
     AnyCharExcept(Vec<char>),
     Alter(Vec<(&'static str, &'static str)>),
+    AlterCi(Vec<(String, String)>),
     AlterString(Vec<(String, String)>),
-    AnyOf(Vec<Rule<T>>),
+    And(N),
+    AnyOf(Vec<N>),
+    CharClass(CharClass),
+    CharIf(fn(char) -> bool),
     CharIn(char, char),
+    CharSet(CharSet),
     Eof,
+    Guard(usize),
     Literal(&'static str),
+    LiteralCi(String),
     LiteralString(String),
     NoBacktrack(String),
-    Not(Rule<T>),
-    Range(u64, u64, Rule<T>),
+    Not(N),
+    Precedence(N, Vec<Operator<N>>),
+    Range(u64, u64, N),
+    SepBy(u64, u64, N, N, SepOptions),
+    Token(I::Kind),
+    TokenIf(fn(&I) -> bool),
+}
+
+impl<N, I: Element> Instr<N, I> {
+    fn is_leaf(&self) -> bool {
+        matches!(self, Instr::AnyChar | Instr::AnyCharExcept(_) | Instr::Alter(_) | Instr::AlterCi(_) | Instr::AlterString(_)
+            | Instr::CharClass(_) | Instr::CharIf(_) | Instr::CharIn(..) | Instr::CharSet(_) | Instr::Eof | Instr::Literal(_)
+            | Instr::LiteralCi(_) | Instr::LiteralString(_) | Instr::Token(_) | Instr::TokenIf(_))
+    }
+
+    // How a failing leaf is reported in the "expected" list of an error.
+    fn expected(&self) -> Vec<String> {
+        match *self {
+            Instr::AnyChar => vec![String::from("any character")],
+            Instr::AnyCharExcept(ref exclude) => {
+                let exclude: Vec<String> = exclude.iter().map(|c| format!("{:?}", c)).collect();
+                vec![format!("any character except {}", exclude.join(", "))]
+            },
+            Instr::Alter(ref list) => list.iter().map(|a| format!("{:?}", a.0)).collect(),
+            Instr::AlterCi(ref list) => list.iter().map(|a| format!("{:?}i", a.0)).collect(),
+            Instr::AlterString(ref list) => list.iter().map(|a| format!("{:?}", a.0)).collect(),
+            Instr::CharClass(class) => vec![class.to_string()],
+            Instr::CharIf(_) => vec![String::from("character")],
+            Instr::CharIn(min, max) if min == max => vec![format!("{:?}", min)],
+            Instr::CharIn(min, max) => vec![format!("{:?}-{:?}", min, max)],
+            Instr::CharSet(ref set) => vec![set.to_string()],
+            Instr::Eof => vec![String::from("end of input")],
+            Instr::Literal(text) => vec![format!("{:?}", text)],
+            Instr::LiteralCi(ref text) => vec![format!("{:?}i", text)],
+            Instr::LiteralString(ref text) => vec![format!("{:?}", text)],
+            Instr::Token(ref kind) => vec![format!("{:?}", kind)],
+            Instr::TokenIf(_) => vec![String::from("token")],
+            Instr::And(_) | Instr::AnyOf(_) | Instr::Guard(_) | Instr::NoBacktrack(_) | Instr::Not(_) | Instr::Precedence(..) | Instr::Range(..) | Instr::SepBy(..) => Vec::new(),
+        }
+    }
+
+    fn map<M>(&self, mut f: impl FnMut(&N) -> M) -> Instr<M, I> {
+        match *self {
+            Instr::AnyChar => Instr::AnyChar,
+            Instr::AnyCharExcept(ref exclude) => Instr::AnyCharExcept(exclude.clone()),
+            Instr::Alter(ref list) => Instr::Alter(list.clone()),
+            Instr::AlterCi(ref list) => Instr::AlterCi(list.clone()),
+            Instr::AlterString(ref list) => Instr::AlterString(list.clone()),
+            Instr::AnyOf(ref rules) => Instr::AnyOf(rules.iter().map(f).collect()),
+            Instr::CharClass(class) => Instr::CharClass(class),
+            Instr::CharIf(f) => Instr::CharIf(f),
+            Instr::CharIn(min, max) => Instr::CharIn(min, max),
+            Instr::CharSet(ref set) => Instr::CharSet(set.clone()),
+            Instr::Eof => Instr::Eof,
+            Instr::Guard(idx) => Instr::Guard(idx),
+            Instr::Literal(text) => Instr::Literal(text),
+            Instr::LiteralCi(ref text) => Instr::LiteralCi(text.clone()),
+            Instr::LiteralString(ref text) => Instr::LiteralString(text.clone()),
+            Instr::NoBacktrack(ref msg) => Instr::NoBacktrack(msg.clone()),
+            Instr::And(ref rule) => Instr::And(f(rule)),
+            Instr::Not(ref rule) => Instr::Not(f(rule)),
+            Instr::Precedence(ref atom, ref ops) => {
+                let atom = f(atom);
+                let ops = ops.iter().map(|op| Operator { fixity: op.fixity, fold: f(&op.fold), op: f(&op.op), power: op.power });
+                Instr::Precedence(atom, ops.collect())
+            },
+            Instr::Range(min, max, ref rule) => Instr::Range(min, max, f(rule)),
+            Instr::SepBy(min, max, ref item, ref sep, options) => Instr::SepBy(min, max, f(item), f(sep), options),
+            Instr::Token(ref kind) => Instr::Token(kind.clone()),
+            Instr::TokenIf(f) => Instr::TokenIf(f),
+        }
+    }
 }
 
 #[derive(Clone)]
 struct ScanErr { 
     idx: usize, 
+    kind: RuleErrorKind,
     msg: String,
 }
 
-struct ScanCtx<'s, T> {
+// Whether trivia is skipped between the elements of a rule. A syntactic rule skips its trivia rule, a 
+// lexical rule and everything it scans doesn't skip anything. Other rules do what the rule scanning them 
+// does.
+enum Mode<N> {
+    Inherit,
+    Lexical,
+    Syntactic(N),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Trivia {
+    Lexical,
+    Off,
+    Skip(usize),
+}
+
+// The farthest index where a leaf failed, and what was expected there.
+#[derive(Default)]
+struct Expected {
+    idx: usize,
+    items: Vec<String>,
+}
+
+// A rule at an index, whether it's scanned inside an `and` or a `not`, and the trivia mode it's scanned
+// in. Lookaheads don't recover or report what they expected, and the same rule skips other trivia, or
+// none, in another mode. Either way the rule can end somewhere else, so a memoized result or a left
+// recursive seed is only reused under the same key.
+type Key = (usize, usize, bool, bool, Trivia);
+
+struct Frame {
+    key: Key,
+    bound: usize,
+    involved: bool,
+    recursed: bool,
+}
+
+struct Memo<'s, T, I: Element + 's> {
+    diagnostics: Vec<ScanErr>,
+    err: Option<ScanErr>,
+    progress: MemoProgress<'s, T, I>,
+}
+
+enum MemoProgress<'s, T, I: Element + 's> {
+    Some { branches: Vec<T>, code_iter: I::Iter<'s>, index: usize, lexeme: String },
+    No,
+    Error { idx: usize, kind: RuleErrorKind, msg: String },
+}
+
+struct ScanCtx<'s, T, I: Element + 's> {
     branches: Vec<T>,
-    code_iter: Chars<'s>,
+    code_iter: I::Iter<'s>,
     index: usize,
+    in_and: bool,
     in_not: bool,
     lexeme: String,
+    trivia: Trivia,
 }
 
-impl<'s, T> ScanCtx<'s, T> {
-    fn new(code: &'s str) -> Self {
+impl<'s, T, I: Element + 's> ScanCtx<'s, T, I> {
+    fn new(code_iter: I::Iter<'s>) -> Self {
         Self {
             branches: Vec::new(),
-            code_iter: code.chars(),
+            code_iter,
             index: 0,
+            in_and: false,
             in_not: false,
             lexeme: String::new(),
+            trivia: Trivia::Off,
         }
     }
 
-    fn branch(self) -> (ScanCtx<'s, T>, ScanCtx<'s, T>) {
-        let new_ctx = ScanCtx {
+    // The next element as a char, tokens don't have one.
+    fn next_char(&mut self) -> Option<char> {
+        self.code_iter.next().and_then(|e| e.as_char())
+    }
+
+    fn branch(self) -> (ScanCtx<'s, T, I>, ScanCtx<'s, T, I>) {
+        (self.fork(), self)
+    }
+
+    fn fork(&self) -> ScanCtx<'s, T, I> {
+        ScanCtx {
             branches: Vec::new(),
             code_iter: self.code_iter.clone(),
             index: self.index,
+            in_and: self.in_and,
             in_not: self.in_not,
             lexeme: String::new(),
-        };
+            trivia: self.trivia,
+        }
+    }
+
+    fn advance(&mut self, mut source: ScanCtx<'s, T, I>, keep_branches: bool) {
+        if keep_branches {
+            self.branches.append(&mut source.branches);
+        }
 
-        (new_ctx, self)
+        self.code_iter = source.code_iter;
+        self.index = source.index;
+        self.lexeme.push_str(&source.lexeme);
+    }
+
+    fn append(mut self, progress: Progress<'s, T, I>) -> Progress<'s, T, I> {
+        match progress {
+            Progress::Some { steps, ctx: mut source } => {
+                self.branches.append(&mut source.branches);
+                self.code_iter = source.code_iter;
+                self.index = source.index;
+                self.lexeme.push_str(&source.lexeme);
+                Progress::Some { steps, ctx: self }
+            },
+            Progress::No(_) => Progress::No(self),
+            Progress::Error { idx, kind, msg } => Progress::Error { idx, kind, msg },
+        }
     }
 
-    fn merge_with(mut self, mut source: ScanCtx<'s, T>, is_rule: bool, branch_fn: &Option<impl Fn(Vec<T>, &str) -> Result<T, String>>) -> Progress<'s, T> {
+    fn merge_with(mut self, mut source: ScanCtx<'s, T, I>, branch_fn: Option<&DynBranchFn<'_, T>>) -> Progress<'s, T, I> {
         let steps = source.index - self.index;
         
         self.code_iter = source.code_iter;
@@ -127,38 +570,90 @@ impl<'s, T> ScanCtx<'s, T> {
         self.lexeme.push_str(&source.lexeme.to_string());
         
         match branch_fn {
-            Some(ref f) if is_rule => {
+            Some(f) => {
+                // A token has at least one char in its lexeme, so the lexeme can be longer than the steps.
                 match f(source.branches, &source.lexeme) {
                     Ok(val) => self.branches.push(val),
-                    Err(msg) => return Progress::Error { idx: source.index - source.lexeme.char_indices().count(), msg },
+                    Err(msg) => return Progress::Error { idx: source.index - source.lexeme.chars().count().min(steps), kind: RuleErrorKind::Semantic, msg },
                 }
             },
-            _ => self.branches.append(&mut source.branches),
+            None => self.branches.append(&mut source.branches),
         }
         
         Progress::Some { steps, ctx: self }
     }
 }
 
-impl<T> Default for Rule<T> {
-    fn default() -> Self {
-        Rule { 
-            0: Rc::new(RefCell::new(_Rule {
-                branch_fn: None,
-                instr: Vec::new(),
-            }))
+impl<'s, T> ScanCtx<'s, T, char> {
+    fn at(code: &'s str, byte_idx: usize, char_idx: usize) -> Self {
+        Self {
+            index: char_idx,
+            ..Self::new(code[byte_idx..].chars())
         }
     }
 }
 
-impl<T> Rule<T> {
+impl<T, I: Element> Default for Rule<T, I> {
+    fn default() -> Self {
+        Rule(Rc::new(RefCell::new(_Rule {
+            branch: None,
+            guards: Vec::new(),
+            instr: Vec::new(),
+            memo: None,
+            mode: Mode::Inherit,
+            name: None,
+            recover: None,
+        })))
+    }
+}
+
+impl<T, I: Element> Rule<T, I> {
+    fn id(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+
+    fn with_branch(branch: Branch<T>) -> Self {
+        let rule = Self::default();
+        rule.0.borrow_mut().branch = Some(branch);
+        rule
+    }
+
     pub fn new(branch_fn: impl Fn(Vec<T>, &str) -> Result<T, String> + 'static) -> Self {
-        Rule { 
-            0: Rc::new(RefCell::new(_Rule {
-                branch_fn: Some(Box::new(branch_fn)),
-                instr: Vec::new(),
-            }))
-        }
+        Self::with_branch(Branch::Lexeme(Box::new(branch_fn)))
+    }
+
+    pub fn named(name: &str, branch_fn: impl Fn(Vec<T>, &str) -> Result<T, String> + 'static) -> Self {
+        let rule = Self::new(branch_fn);
+        rule.set_name(name);
+        rule
+    }
+
+    pub fn new_sync(branch_fn: impl Fn(Vec<T>, &str) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        Self::with_branch(Branch::Sync(SyncBranch::Lexeme(Arc::new(branch_fn))))
+    }
+
+    pub fn with_span(branch_fn: impl Fn(Vec<T>, &str, Span) -> Result<T, String> + 'static) -> Self {
+        Self::with_branch(Branch::Span(Box::new(branch_fn)))
+    }
+
+    pub fn with_span_sync(branch_fn: impl Fn(Vec<T>, &str, Span) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        Self::with_branch(Branch::Sync(SyncBranch::Span(Arc::new(branch_fn))))
+    }
+
+    // The branch function gets the state of `scan_with_state`, scanning the rule in another way is an error.
+    pub fn with_state<S: 'static>(branch_fn: impl Fn(&mut S, Vec<T>, &str) -> Result<T, String> + 'static) -> Self {
+        let state_fn = move |state: &mut dyn Any, branches, lexeme: &str| match state.downcast_mut::<S>() {
+            Some(state) => branch_fn(state, branches, lexeme),
+            None => Err(String::from("State has another type than the rule expects.")),
+        };
+
+        Self::with_branch(Branch::State(Box::new(state_fn)))
+    }
+
+    pub fn and(&self, rule: &Rule<T, I>) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::And(rule.clone()));
+        self
     }
 
     pub fn any_char(&self) -> &Self {
@@ -166,222 +661,990 @@ impl<T> Rule<T> {
         r.instr.push(Instr::AnyChar);
         self
     }
-    
-    pub fn any_char_except(&self, exclude: Vec<char>) -> &Self {
-        if exclude.len() == 0 {
-            panic!("List of excluded characters is empty.");
-        }
-        
+
+    pub fn any_of(&self, rules: Vec<&Rule<T, I>>) -> &Self {
+        or_panic(self.try_any_of(rules))
+    }
+
+    pub fn at_least(&self, count: u64, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::AnyCharExcept(exclude));
+        r.instr.push(Instr::Range(count, u64::MAX, rule.clone()));
         self
     }
-    
-    pub fn alter(&self, list: Vec<(&'static str, &'static str)>) -> &Self {
-        if list.len() == 0 {
-            panic!("List is empty.");
-        }
-        
-        if !list.iter().any(|t| { t.0.len() > 0 && t.1.len() > 0 }) {
-            panic!("The strings in the list must be minimal one character long.");
-        }
-        
+
+    pub fn at_most(&self, count: u64, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Alter(list));
+        r.instr.push(Instr::Range(0, count, rule.clone()));
         self
     }
 
-    pub fn alter_string(&self, list: Vec<(String, String)>) -> &Self {
-        if list.len() == 0 {
-            panic!("List is empty.");
-        }
-        
-        if !list.iter().any(|ref t| { t.0.len() > 0 && t.1.len() > 0 }) {
-            panic!("The strings in the list must be minimal one character long.");
-        }
-        
+    pub fn between(&self, min: u64, max: u64, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::AlterString(list));
+        r.instr.push(Instr::Range(min, max, rule.clone()));
         self
     }
-    
-    pub fn any_of(&self, rules: Vec<&Rule<T>>) -> &Self {
+
+    pub fn eof(&self) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::Eof);
+        self
+    }
+
+    pub fn exact(&self, count: u64, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::Range(count, count, rule.clone()));
+        self
+    }
 
-        match rules.len() {
-            0 => panic!("You must specify rules."),
-            1 => r.instr.push(Instr::Range(1, 1, rules[0].clone())),
-            _ => r.instr.push(Instr::AnyOf(rules.into_iter().map(|x| x.clone()).collect())),  
+    pub fn freeze(&self) -> Result<FrozenRule<T, I>, GrammarError> {
+        FrozenRule::new(self)
+    }
+
+    pub fn guard(&self, f: impl Fn(&str, &[T]) -> bool + 'static) -> &Self {
+        self.push_guard(Guard::Lexeme(Box::new(f)))
+    }
+
+    pub fn guard_sync(&self, f: impl Fn(&str, &[T]) -> bool + Send + Sync + 'static) -> &Self {
+        self.push_guard(Guard::Sync(Arc::new(f)))
+    }
+
+    // The guard reads the state of `scan_with_state`, which the branch functions of `Rule::with_state` change.
+    pub fn guard_with_state<S: 'static>(&self, f: impl Fn(&S, &str, &[T]) -> bool + 'static) -> &Self {
+        let guard_fn = move |state: &dyn Any, lexeme: &str, branches: &[T]| match state.downcast_ref::<S>() {
+            Some(state) => Ok(f(state, lexeme, branches)),
+            None => Err(String::from("State has another type than the rule expects.")),
         };
 
+        self.push_guard(Guard::State(Box::new(guard_fn)))
+    }
+
+    fn push_guard(&self, guard: Guard<T>) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.guards.push(guard);
+        let idx = r.guards.len() - 1;
+        r.instr.push(Instr::Guard(idx));
+        self
+    }
+
+    pub fn lexical(&self) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.mode = Mode::Lexical;
         self
     }
-    
-    pub fn at_least(&self, count: u64, rule: &Rule<T>) -> &Self {
+
+    pub fn maybe(&self, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(count, u64::max_value(), rule.clone()));
+        r.instr.push(Instr::Range(0, 1, rule.clone()));
         self
     }
-    
-    pub fn at_most(&self, count: u64, rule: &Rule<T>) -> &Self {
+
+    pub fn memoize(&self) -> &Self where T: Clone {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(0, count, rule.clone()));
+        r.memo = Some(<[T]>::to_vec);
         self
     }
-    
-    pub fn between(&self, min: u64, max: u64, rule: &Rule<T>) -> &Self {
+
+    pub fn no_backtrack(&self, err_msg: String) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(min, max, rule.clone()));
+        r.instr.push(Instr::NoBacktrack(err_msg));
         self
     }
-    
-    pub fn char_in(&self, min: char, max: char) -> &Self {
+
+    pub fn none_or_many(&self, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::CharIn(min, max));
+        r.instr.push(Instr::Range(0, u64::MAX, rule.clone()));
         self
     }
-    
-    pub fn eof(&self) -> &Self {
+
+    pub fn not(&self, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Eof);
+        r.instr.push(Instr::Not(rule.clone()));
         self
     }
-    
-    pub fn exact(&self, count: u64, rule: &Rule<T>) -> &Self {
+
+    pub fn one(&self, rule: &Rule<T, I>) -> &Self {
         let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(count, count, rule.clone()));
+        r.instr.push(Instr::Range(1, 1, rule.clone()));
         self
     }
-    
-    pub fn literal(&self, text: &'static str) -> &Self {
-        if text.len() < 1 {
-            panic!("Literal text must at least 1 character long.");
+
+    pub fn peek(&self, rule: &Rule<T, I>) -> &Self {
+        self.and(rule)
+    }
+
+    pub fn recover_to(&self, sync: &Rule<T, I>) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.recover = Some(sync.clone());
+        self
+    }
+
+    pub fn sep_by(&self, min: u64, max: u64, item: &Rule<T, I>, sep: &Rule<T, I>) -> &Self {
+        self.sep_by_with(min, max, item, sep, SepOptions::default())
+    }
+
+    pub fn sep_by_with(&self, min: u64, max: u64, item: &Rule<T, I>, sep: &Rule<T, I>, options: SepOptions) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::SepBy(min, max, item.clone(), sep.clone(), options));
+        self
+    }
+
+    pub fn set_name(&self, name: &str) -> &Self {
+        or_panic(self.try_set_name(name))
+    }
+
+    pub fn token(&self, kind: I::Kind) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::Token(kind));
+        self
+    }
+
+    pub fn token_if(&self, f: fn(&I) -> bool) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::TokenIf(f));
+        self
+    }
+
+    pub fn trivia(&self, trivia: &Rule<T, I>) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.mode = Mode::Syntactic(trivia.clone());
+        self
+    }
+
+    pub fn try_any_of(&self, rules: Vec<&Rule<T, I>>) -> Result<&Self, GrammarError> {
+        let mut r = self.0.borrow_mut();
+
+        match rules.len() {
+            0 => return Err(GrammarError::Invalid(String::from("You must specify rules."))),
+            1 => r.instr.push(Instr::Range(1, 1, rules[0].clone())),
+            _ => r.instr.push(Instr::AnyOf(rules.into_iter().cloned().collect())),  
+        };
+
+        Ok(self)
+    }
+
+    pub fn try_set_name(&self, name: &str) -> Result<&Self, GrammarError> {
+        if name.is_empty() {
+            return Err(GrammarError::Invalid(String::from("Name must at least 1 character long.")));
+        }
+
+        let mut r = self.0.borrow_mut();
+        r.name = Some(name.to_string());
+        Ok(self)
+    }
+
+    pub fn validate(&self) -> Vec<GrammarIssue> {
+        validate::validate(self)
+    }
+}
+
+impl<T> Rule<T> {
+    pub fn any_char_except(&self, exclude: Vec<char>) -> &Self {
+        or_panic(self.try_any_char_except(exclude))
+    }
+
+    pub fn alter(&self, list: Vec<(&'static str, &'static str)>) -> &Self {
+        or_panic(self.try_alter(list))
+    }
+
+    pub fn alter_ci(&self, list: Vec<(&'static str, &'static str)>) -> &Self {
+        or_panic(self.try_alter_ci(list))
+    }
+
+    pub fn alter_string(&self, list: Vec<(String, String)>) -> &Self {
+        or_panic(self.try_alter_string(list))
+    }
+
+    pub fn alter_string_ci(&self, list: Vec<(String, String)>) -> &Self {
+        or_panic(self.try_alter_string_ci(list))
+    }
+
+    pub fn char_class(&self, class: CharClass) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::CharClass(class));
+        self
+    }
+
+    pub fn char_if(&self, f: fn(char) -> bool) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::CharIf(f));
+        self
+    }
+
+    pub fn char_in(&self, min: char, max: char) -> &Self {
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::CharIn(min, max));
+        self
+    }
+
+    pub fn char_set(&self, ranges: Vec<(char, char)>) -> &Self {
+        or_panic(self.try_char_set(ranges))
+    }
+
+    pub fn char_set_except(&self, ranges: Vec<(char, char)>) -> &Self {
+        or_panic(self.try_char_set_except(ranges))
+    }
+
+    pub fn char_set_expr(&self, expr: &str) -> &Self {
+        or_panic(self.try_char_set_expr(expr))
+    }
+
+    fn push_char_set(&self, negated: bool, ranges: Vec<(char, char)>) -> Result<&Self, GrammarError> {
+        if ranges.is_empty() {
+            return Err(GrammarError::Invalid(String::from("List of ranges is empty.")));
+        }
+
+        if ranges.iter().any(|&(min, max)| min > max) {
+            return Err(GrammarError::Invalid(String::from("Invalid character range.")));
+        }
+
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::CharSet(CharSet::new(negated, ranges)));
+        Ok(self)
+    }
+
+    pub fn literal(&self, text: &'static str) -> &Self {
+        or_panic(self.try_literal(text))
+    }
+
+    pub fn literal_ci(&self, text: &'static str) -> &Self {
+        or_panic(self.try_literal_ci(text))
+    }
+
+    pub fn literal_string(&self, text: String) -> &Self {
+        or_panic(self.try_literal_string(text))
+    }
+
+    pub fn literal_string_ci(&self, text: String) -> &Self {
+        or_panic(self.try_literal_string_ci(text))
+    }
+
+    pub fn scan(&self, code: &str) -> Result<Vec<T>, RuleError> {
+        scan(&RuleGraph(PhantomData), self, code, None)
+    }
+
+    pub fn scan_prefix(&self, code: &str) -> Result<(Vec<T>, usize, usize), RuleError> {
+        scan_prefix(&RuleGraph(PhantomData), self, code)
+    }
+
+    pub fn scan_recover(&self, code: &str) -> (Option<Vec<T>>, Vec<RuleError>) {
+        scan_recover(&RuleGraph(PhantomData), self, code)
+    }
+
+    pub fn scan_traced(&self, code: &str, observer: &mut impl ScanObserver) -> Result<Vec<T>, RuleError> {
+        scan(&RuleGraph(PhantomData), self, code, Some(observer))
+    }
+
+    pub fn scan_with_state<S: Clone + 'static>(&self, code: &str, state: &mut S) -> Result<Vec<T>, RuleError> {
+        scan_with_state(&RuleGraph(PhantomData), self, code, State::new(state))
+    }
+
+    pub fn try_alter(&self, list: Vec<(&'static str, &'static str)>) -> Result<&Self, GrammarError> {
+        check_alter(&list)?;
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::Alter(list));
+        Ok(self)
+    }
+
+    pub fn try_alter_ci(&self, list: Vec<(&'static str, &'static str)>) -> Result<&Self, GrammarError> {
+        self.try_alter_string_ci(list.into_iter().map(|(find, replace)| (find.to_string(), replace.to_string())).collect())
+    }
+
+    pub fn try_alter_string(&self, list: Vec<(String, String)>) -> Result<&Self, GrammarError> {
+        check_alter(&list)?;
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::AlterString(list));
+        Ok(self)
+    }
+
+    pub fn try_alter_string_ci(&self, list: Vec<(String, String)>) -> Result<&Self, GrammarError> {
+        check_alter(&list)?;
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::AlterCi(list));
+        Ok(self)
+    }
+
+    pub fn try_any_char_except(&self, exclude: Vec<char>) -> Result<&Self, GrammarError> {
+        if exclude.is_empty() {
+            return Err(GrammarError::Invalid(String::from("List of excluded characters is empty.")));
+        }
+        
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::AnyCharExcept(exclude));
+        Ok(self)
+    }
+
+    pub fn try_char_set(&self, ranges: Vec<(char, char)>) -> Result<&Self, GrammarError> {
+        self.push_char_set(false, ranges)
+    }
+
+    pub fn try_char_set_except(&self, ranges: Vec<(char, char)>) -> Result<&Self, GrammarError> {
+        self.push_char_set(true, ranges)
+    }
+
+    pub fn try_char_set_expr(&self, expr: &str) -> Result<&Self, GrammarError> {
+        match grammar::parse_char_set(expr) {
+            Ok((negated, ranges)) => self.push_char_set(negated, ranges),
+            Err(err) => Err(GrammarError::Invalid(format!("Invalid character set: {}", err))),
+        }
+    }
+
+    pub fn try_literal(&self, text: &'static str) -> Result<&Self, GrammarError> {
+        if text.is_empty() {
+            return Err(GrammarError::Invalid(String::from("Literal text must at least 1 character long.")));
+        }
+
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::Literal(text));
+        Ok(self)
+    }
+
+    pub fn try_literal_ci(&self, text: &'static str) -> Result<&Self, GrammarError> {
+        self.try_literal_string_ci(text.to_string())
+    }
+
+    pub fn try_literal_string(&self, text: String) -> Result<&Self, GrammarError> {
+        if text.is_empty() {
+            return Err(GrammarError::Invalid(String::from("Literal text must at least 1 character long.")));
+        }
+
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::LiteralString(text));
+        Ok(self)
+    }
+
+    pub fn try_literal_string_ci(&self, text: String) -> Result<&Self, GrammarError> {
+        if text.is_empty() {
+            return Err(GrammarError::Invalid(String::from("Literal text must at least 1 character long.")));
+        }
+
+        let mut r = self.0.borrow_mut();
+        r.instr.push(Instr::LiteralCi(text));
+        Ok(self)
+    }
+
+    // Like `scan`, but a rule without instructions anywhere in the graph is an error instead of a panic.
+    pub fn try_scan(&self, code: &str) -> Result<Vec<T>, GrammarError> {
+        if let Some(rule) = validate::collect(self).into_iter().find(|r| r.0.borrow().instr.is_empty()) {
+            return Err(GrammarError::Undefined(rule.0.borrow().name.clone()));
+        }
+
+        self.scan(code).map_err(GrammarError::Scan)
+    }
+}
+
+impl<T, I: Token> Rule<T, I> {
+    pub fn scan_tokens(&self, tokens: &[I], source: &str) -> Result<Vec<T>, RuleError> {
+        scan_tokens(&RuleGraph(PhantomData), self, tokens, source)
+    }
+}
+
+fn scan<'g, T, G: Graph<T, char>>(graph: &'g G, root: &G::Node, code: &str, observer: Option<&'g mut dyn ScanObserver>) -> Result<Vec<T>, RuleError> {
+    let mut scanner = Scanner::new(graph, code);
+    scanner.observer = observer.map(RefCell::new);
+
+    match scan_root(scanner, root, ScanCtx::new(code.chars()), true) {
+        (Some(ctx), errors) if errors.is_empty() => Ok(ctx.branches),
+        (_, mut errors) => Err(errors.remove(0)),
+    }
+}
+
+// Branch functions of `Rule::with_state` change the state, the changes of rules which fail are undone.
+fn scan_with_state<'g, T, G: Graph<T, char>>(graph: &'g G, root: &G::Node, code: &str, state: State<'g>) -> Result<Vec<T>, RuleError> {
+    let mut scanner = Scanner::new(graph, code);
+    scanner.state = Some(RefCell::new(state));
+
+    match scan_root(scanner, root, ScanCtx::new(code.chars()), true) {
+        (Some(ctx), errors) if errors.is_empty() => Ok(ctx.branches),
+        (_, mut errors) => Err(errors.remove(0)),
+    }
+}
+
+fn scan_prefix<T, G: Graph<T, char>>(graph: &G, root: &G::Node, code: &str) -> Result<(Vec<T>, usize, usize), RuleError> {
+    let ctx = match scan_root(Scanner::new(graph, code), root, ScanCtx::new(code.chars()), false) {
+        (Some(ctx), errors) if errors.is_empty() => ctx,
+        (_, mut errors) => return Err(errors.remove(0)),
+    };
+
+    let bytes = code.len() - ctx.code_iter.as_str().len();
+    let chars = code[..bytes].chars().count();
+    Ok((ctx.branches, bytes, chars))
+}
+
+fn scan_recover<T, G: Graph<T, char>>(graph: &G, root: &G::Node, code: &str) -> (Option<Vec<T>>, Vec<RuleError>) {
+    let (ctx, errors) = scan_root(Scanner::new(graph, code), root, ScanCtx::new(code.chars()), true);
+    (ctx.map(|ctx| ctx.branches), errors)
+}
+
+// Tokens are scanned like chars, but errors and spans are mapped to the source through the spans of the 
+// tokens.
+fn scan_tokens<T, I: Token, G: Graph<T, I>>(graph: &G, root: &G::Node, tokens: &[I], source: &str) -> Result<Vec<T>, RuleError> {
+    let mut scanner = Scanner::new(graph, source);
+    scanner.token_spans = Some(tokens.iter().map(Token::span).collect());
+
+    match scan_root(scanner, root, ScanCtx::new(tokens.iter().cloned()), true) {
+        (Some(ctx), errors) if errors.is_empty() => Ok(ctx.branches),
+        (_, mut errors) => Err(errors.remove(0)),
+    }
+}
+
+// Returns the scanned context unless the scan failed, and the errors recovered from followed by the 
+// error the scan failed with.
+fn scan_root<'s, T, I: Element, G: Graph<T, I>>(scanner: Scanner<'s, '_, T, I, G>, root: &G::Node, ctx: ScanCtx<'s, T, I>, eof: bool) -> (Option<ScanCtx<'s, T, I>>, Vec<RuleError>) {
+    if scanner.graph.body(root).instr().is_empty() {
+        panic!("Rule is not defined.");
+    }
+
+    let result = scanner.run_root(root, ctx, eof);
+    let mut errors: Vec<RuleError> = scanner.diagnostics.take().into_iter().map(|e| scanner.error(e.idx, e.kind, e.msg)).collect();
+
+    match result {
+        Ok(ctx) => (Some(ctx), errors),
+        Err(err) => {
+            errors.push(err);
+            (None, errors)
+        },
+    }
+}
+
+struct Scanner<'s, 'g, T, I: Element + 's, G: Graph<T, I>> { 
+    code: &'s str,
+    diagnostics: RefCell<Vec<ScanErr>>,
+    err: RefCell<ScanErr>,
+    err_writes: Cell<usize>,
+    expected: RefCell<Expected>,
+    frames: RefCell<Vec<Frame>>,
+    graph: &'g G,
+    lines: OnceCell<Vec<(usize, usize)>>,
+    memo: RefCell<HashMap<Key, Memo<'s, T, I>>>,
+    observer: Option<RefCell<&'g mut dyn ScanObserver>>,
+    state: Option<RefCell<State<'g>>>,
+    token_spans: Option<Vec<Range<usize>>>,
+    trivia: RefCell<HashMap<usize, G::Node>>,
+}
+
+impl<'s, 'g, T, I: Element + 's, G: Graph<T, I>> Scanner<'s, 'g, T, I, G> {
+    fn new(graph: &'g G, code: &'s str) -> Self {
+        Scanner {
+            code,
+            diagnostics: RefCell::new(Vec::new()),
+            err: RefCell::new(ScanErr { idx: 0, kind: RuleErrorKind::Syntax, msg: String::from("Syntax error.") }),
+            err_writes: Cell::new(0),
+            expected: RefCell::new(Expected::default()),
+            frames: RefCell::new(Vec::new()),
+            graph,
+            lines: OnceCell::new(),
+            memo: RefCell::new(HashMap::new()),
+            observer: None,
+            state: None,
+            token_spans: None,
+            trivia: RefCell::new(HashMap::new()),
+        }
+    }
+
+    fn run_root(&self, root: &G::Node, ctx: ScanCtx<'s, T, I>, eof: bool) -> Result<ScanCtx<'s, T, I>, RuleError> {
+        // Trivia at the end of the input is skipped when the root is a syntactic rule.
+        let trivia = match *self.graph.body(root).mode() {
+            Mode::Syntactic(ref trivia) if eof => Some(self.graph.id(trivia)),
+            _ => None,
+        };
+
+        let progress = match (self.run(root, ctx), trivia) {
+            (Progress::Some { steps: _, ctx }, Some(id)) => self.skip_trivia(id, ctx),
+            (progress, _) => progress,
+        };
+
+        match progress {
+            Progress::Some { steps: _, ctx } if eof && ctx.code_iter.clone().next().is_some() => {
+                // The root would have to be followed by the end of the input.
+                let mut expected = self.expected.take();
+
+                if expected.idx < ctx.index {
+                    expected = Expected { idx: ctx.index, items: Vec::new() };
+                }
+
+                if expected.idx == ctx.index {
+                    expected.items.push(String::from("end of input"));
+                }
+
+                Err(self.expected_error(expected.idx, RuleErrorKind::TrailingInput, expected.items))
+            },
+            Progress::Some { steps: _, ctx } => Ok(ctx),
+            Progress::No(ctx) => {
+                let expected = self.expected.take();
+
+                if expected.items.is_empty() {
+                    Err(self.error(ctx.index, RuleErrorKind::Syntax, String::from("Syntax error.")))
+                }
+                else {
+                    Err(self.expected_error(expected.idx, RuleErrorKind::Syntax, expected.items))
+                }
+            },
+            Progress::Error { idx, kind, msg } => Err(self.error(idx, kind, msg)),
+        }
+    }
+
+    // A rule scanned by a syntactic rule comes after its trivia. The trivia is given back when the rule
+    // doesn't match.
+    // A rule which fails is a point where the scanner backtracks, so the state is rolled back.
+    fn run(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        self.push_state();
+        let progress = self.run_after_trivia(rule, ctx);
+        self.pop_state(!matches!(progress, Progress::Some { .. }));
+        progress
+    }
+
+    fn run_after_trivia(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let id = match ctx.trivia {
+            Trivia::Skip(id) => id,
+            _ => return self.run_observed(rule, ctx),
+        };
+
+        let (code_iter, index) = (ctx.code_iter.clone(), ctx.index);
+
+        match self.skip_trivia(id, ctx) {
+            Progress::Some { steps: _, ctx } => match self.run_observed(rule, ctx) {
+                Progress::No(mut ctx) => {
+                    ctx.code_iter = code_iter;
+                    ctx.index = index;
+                    Progress::No(ctx)
+                },
+                progress => progress,
+            },
+            progress => progress,
+        }
+    }
+
+    fn run_observed(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let observer = match self.observer {
+            Some(ref observer) => observer,
+            None => return self.run_node(rule, ctx),
+        };
+
+        let body = self.graph.body(rule);
+        let info = RuleInfo { id: self.graph.id(rule), name: body.name() };
+        let start = ctx.index;
+        observer.borrow_mut().enter(&info, start);
+        let progress = self.run_node(rule, ctx);
+
+        match progress {
+            Progress::Some { ref ctx, .. } => observer.borrow_mut().success(&info, start, ctx.index),
+            _ => observer.borrow_mut().fail(&info, start),
+        }
+
+        progress
+    }
+
+    fn run_node(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let key = (self.graph.id(rule), ctx.index, ctx.in_and, ctx.in_not, ctx.trivia);
+        let memo = self.graph.body(rule).memo();
+
+        if let Some(pos) = self.find_frame(key) {
+            return self.recurse(rule, pos, memo, ctx);
+        }
+
+        if let Some(clone) = memo.filter(|_| self.memo.borrow().contains_key(&key)) {
+            return self.memo_hit(key, clone, ctx);
+        }
+
+        let err = self.err.borrow().clone();
+        let err_writes = self.err_writes.get();
+        let state_writes = self.state_writes();
+        let diagnostics = self.diagnostics.borrow().len();
+        self.frames.borrow_mut().push(Frame { key, bound: 0, involved: false, recursed: false });
+        self.push_state();
+        let mut progress = self.run_rule(rule, ctx.fork());
+
+        if self.frames.borrow().last().unwrap().recursed {
+            progress = self.grow(rule, memo, &ctx, progress, diagnostics);
+        }
+
+        self.pop_state(false);
+        let frame = self.frames.borrow_mut().pop().unwrap();
+
+        match progress {
+            Progress::No(_) => self.diagnostics.borrow_mut().truncate(diagnostics),
+            Progress::Error { idx, kind, msg } => {
+                progress = match self.graph.body(rule).recover() {
+                    Some(sync) if !ctx.in_and && !ctx.in_not => self.recover(sync, &ctx, ScanErr { idx, kind, msg }, err),
+                    _ => Progress::Error { idx, kind, msg },
+                };
+            },
+            Progress::Some { .. } => (),
+        }
+
+        if let Some(clone) = memo {
+            // A replayed rule doesn't call its branch functions, so it can't be replayed when it changed the state.
+            if frame.involved || self.state_writes() != state_writes {
+                self.memo.borrow_mut().remove(&key);
+            }
+            else {
+                let err = if self.err_writes.get() == err_writes { None } else { Some(self.err.borrow().clone()) };
+                let diagnostics = self.diagnostics.borrow()[diagnostics..].to_vec();
+                self.memo_store(key, clone, &progress, err, diagnostics);
+            }
+        }
+
+        ctx.append(progress)
+    }
+
+    // Left recursion, the rule is entered again at the same index. The first time around this fails and
+    // marks the rule for growing the seed. While growing, the recursion is bounded by the number of 
+    // iterations so far, or it replays the last seed when the rule is memoized.
+    fn recurse(&self, rule: &G::Node, pos: usize, memo: Option<CloneFn<T>>, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let bound = {
+            let mut frames = self.frames.borrow_mut();
+            frames[pos + 1..].iter_mut().for_each(|f| f.involved = true);
+            frames[pos].recursed = true;
+            frames[pos].bound
+        };
+
+        if bound == 0 {
+            return Progress::No(ctx);
+        }
+
+        if let Some(clone) = memo {
+            return self.memo_hit(self.frames.borrow()[pos].key, clone, ctx);
+        }
+
+        // The rules in between are scanned again from the start, so they're put aside meanwhile.
+        let between = {
+            let mut frames = self.frames.borrow_mut();
+            frames[pos].bound = bound - 1;
+            frames.split_off(pos + 1)
+        };
+
+        let progress = self.run_rule(rule, ctx.fork());
+
+        {
+            let mut frames = self.frames.borrow_mut();
+            frames[pos].bound = bound;
+            frames.extend(between);
+        }
+
+        ctx.append(progress)
+    }
+
+    fn grow(&self, rule: &G::Node, memo: Option<CloneFn<T>>, ctx: &ScanCtx<'s, T, I>, mut progress: Progress<'s, T, I>, diagnostics: usize) -> Progress<'s, T, I> {
+        loop {
+            let index = match progress {
+                Progress::Some { ctx: ref seed, .. } => seed.index,
+                _ => return progress,
+            };
+
+            // The errors recovered from in the seed are recovered from again when it's scanned again.
+            let seed_diagnostics = self.diagnostics.borrow_mut().split_off(diagnostics);
+
+            if let Some(clone) = memo {
+                let key = self.frames.borrow().last().unwrap().key;
+                self.memo_store(key, clone, &progress, None, seed_diagnostics.clone());
+            }
+
+            self.frames.borrow_mut().last_mut().unwrap().bound += 1;
+
+            // Every iteration scans the seed again, so it starts without the no backtrack points of the 
+            // previous one. Failing to grow is no error, unless it failed after a no backtrack point beyond
+            // the seed.
+            let err = self.err.replace(ScanErr { idx: ctx.index, kind: RuleErrorKind::Syntax, msg: String::new() });
+            let err_writes = self.err_writes.get();
+
+            // The state changes of the seed are made again by the iteration, unless the seed is replayed
+            // from the memo. They're put back when the seed doesn't grow.
+            let seed_changes = match (memo, &self.state) {
+                (None, Some(state)) => state.borrow_mut().undo(),
+                _ => None,
+            };
+
+            self.push_state();
+            let attempt = self.run_rule(rule, ctx.fork());
+            let grown = matches!(attempt, Progress::Some { ctx: ref grown, .. } if grown.index > index);
+            self.pop_state(!grown);
+
+            if let (false, Some(state)) = (grown, &self.state) {
+                state.borrow_mut().redo(seed_changes);
+            }
+
+            match attempt {
+                Progress::Some { steps, ctx: grown } if grown.index > index => {
+                    if self.err_writes.get() == err_writes {
+                        self.err.replace(err);
+                    }
+
+                    progress = Progress::Some { steps, ctx: grown };
+                },
+                Progress::Error { idx, kind, msg } if idx > index => return Progress::Error { idx, kind, msg },
+                _ => {
+                    self.err.replace(err);
+                    let mut d = self.diagnostics.borrow_mut();
+                    d.truncate(diagnostics);
+                    d.extend(seed_diagnostics);
+                    return progress;
+                },
+            }
+        }
+    }
+
+    fn expect(&self, idx: usize, instr: &Instr<G::Node, I>) {
+        let mut expected = self.expected.borrow_mut();
+
+        if idx < expected.idx {
+            return;
+        }
+
+        let items = instr.expected();
+
+        if items.is_empty() {
+            return;
+        }
+
+        if idx > expected.idx {
+            *expected = Expected { idx, items: Vec::new() };
+        }
+
+        for item in items {
+            if !expected.items.contains(&item) {
+                expected.items.push(item);
+            }
+        }
+    }
+
+    // A named rule which fails at its start is expected by name, instead of the leaves it tried.
+    fn expect_name(&self, idx: usize, name: &str, (prev_idx, prev_len): (usize, usize)) {
+        let mut expected = self.expected.borrow_mut();
+
+        if idx < expected.idx {
+            return;
+        }
+
+        if prev_idx == idx {
+            expected.items.truncate(prev_len);
+        }
+        else {
+            *expected = Expected { idx, items: Vec::new() };
+        }
+
+        if !expected.items.iter().any(|i| i == name) {
+            expected.items.push(name.to_string());
         }
+    }
 
-        let mut r = self.0.borrow_mut();   
-        r.instr.push(Instr::Literal(&text));
-        self
+    fn find_frame(&self, key: Key) -> Option<usize> {
+        let frames = self.frames.borrow();
+
+        frames.iter()
+            .enumerate()
+            .rev()
+            .take_while(|(_, f)| f.key.1 == key.1)
+            .find(|(_, f)| f.key == key)
+            .map(|(pos, _)| pos)
     }
 
-    pub fn literal_string(&self, text: String) -> &Self {
-        if text.len() < 1 {
-            panic!("Literal text must at least 1 character long.");
+    fn memo_hit(&self, key: Key, clone: CloneFn<T>, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let memo = self.memo.borrow();
+        let memo = &memo[&key];
+
+        if let Some(ref err) = memo.err {
+            self.set_err(err.clone());
         }
-            
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::LiteralString(text));
-        self
-    }
 
-    pub fn maybe(&self, rule: &Rule<T>) -> &Self {
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(0, 1, rule.clone()));
-        self
-    }
+        self.diagnostics.borrow_mut().extend(memo.diagnostics.iter().cloned());
 
-    pub fn no_backtrack(&self, err_msg: String) -> &Self {
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::NoBacktrack(err_msg));
-        self
+        match memo.progress {
+            MemoProgress::Some { ref branches, ref code_iter, index, ref lexeme } => {
+                let steps = index - ctx.index;
+                ctx.branches.append(&mut clone(branches));
+                ctx.code_iter = code_iter.clone();
+                ctx.index = index;
+                ctx.lexeme.push_str(lexeme);
+                Progress::Some { steps, ctx }
+            },
+            MemoProgress::No => self.no_or_error(ctx),
+            MemoProgress::Error { idx, ref kind, ref msg } => Progress::Error { idx, kind: kind.clone(), msg: msg.clone() },
+        }
     }
 
-    pub fn none_or_many(&self, rule: &Rule<T>) -> &Self {
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(0, u64::max_value(), rule.clone()));
-        self
-    }
-    
-    pub fn not(&self, rule: &Rule<T>) -> &Self {
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Not(rule.clone()));
-        self
-    }
-    
-    pub fn one(&self, rule: &Rule<T>) -> &Self {
-        let mut r = self.0.borrow_mut();
-        r.instr.push(Instr::Range(1, 1, rule.clone()));
-        self
+    fn memo_store(&self, key: Key, clone: CloneFn<T>, progress: &Progress<'s, T, I>, err: Option<ScanErr>, diagnostics: Vec<ScanErr>) {
+        let progress = match *progress {
+            Progress::Some { ref ctx, .. } => MemoProgress::Some { 
+                branches: clone(&ctx.branches),
+                code_iter: ctx.code_iter.clone(),
+                index: ctx.index,
+                lexeme: ctx.lexeme.clone(),
+            },
+            Progress::No(_) => MemoProgress::No,
+            Progress::Error { idx, ref kind, ref msg } => MemoProgress::Error { idx, kind: kind.clone(), msg: msg.clone() },
+        };
+
+        self.memo.borrow_mut().insert(key, Memo { diagnostics, err, progress });
     }
 
-    pub fn scan(&self, code: &str) -> Result<Vec<T>, RuleError> {
-        let r = self.0.borrow();
-            
-        if r.instr.len() == 0 {
-            panic!("Rule is not defined.");
-        }
-        
-        let mut ctx = ScanCtx::new(code);
-        let scanner = Scanner::new();
+    // Remembers the error and skips to the end of the first match of the sync rule, starting at the index 
+    // of the error. The error state is restored to the one before the failed rule. The failed rule has no
+    // branches.
+    fn recover(&self, sync: &G::Node, ctx: &ScanCtx<'s, T, I>, failure: ScanErr, err: ScanErr) -> Progress<'s, T, I> {
+        let mut skip = ctx.fork();
 
-        match scanner.run(self, ctx) {
-            Progress::Some { steps: _, ctx: new_ctx } => ctx = new_ctx,
-            Progress::No(new_ctx) => return Err(RuleError::new(code, new_ctx.index, String::from("Syntax error."))),
-            Progress::Error { idx, msg } => return Err(RuleError::new(code, idx, msg)),
+        while skip.index < failure.idx && skip.code_iter.next().is_some() {
+            skip.index += 1;
         }
-        
-        if let Some(_) = ctx.code_iter.next() {
-            Err(RuleError::new(code, ctx.index, String::from("Syntax error.")))
-        }
-        else {
-            Ok(ctx.branches)
+
+        let expected = self.expected.take();
+
+        loop {
+            let attempt = self.run(sync, skip.fork());
+            self.err.replace(err.clone());
+
+            match attempt {
+                Progress::Some { ctx: synced, .. } => {
+                    skip.code_iter = synced.code_iter;
+                    skip.index = synced.index;
+                    break;
+                },
+                _ if skip.code_iter.next().is_some() => skip.index += 1,
+                _ => break,
+            }
         }
-    }
-}
 
-struct Scanner { 
-    err: RefCell<ScanErr>,
-}
+        self.expected.replace(expected);
 
-impl Scanner {
-    fn new() -> Self {
-        Scanner {
-            err: RefCell::new(ScanErr { idx: 0, msg: String::from("Syntax error.") }),
+        if skip.index == ctx.index {
+            return Progress::Error { idx: failure.idx, kind: failure.kind, msg: failure.msg };
+        }
+
+        for e in ctx.code_iter.clone().take(skip.index - ctx.index) {
+            e.push_lexeme(self.code, &mut skip.lexeme);
         }
+
+        self.diagnostics.borrow_mut().push(failure);
+        Progress::Some { steps: skip.index - ctx.index, ctx: skip }
     }
 
-    fn run<'s, T>(&self, rule: &Rule<T>, ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
-        let r = rule.0.borrow();
+    fn run_rule(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let r = self.graph.body(rule);
         let (mut new_ctx, ctx) = ctx.branch();
+
+        match *r.mode() {
+            Mode::Inherit => (),
+            Mode::Syntactic(ref trivia) if ctx.trivia != Trivia::Lexical => {
+                let id = self.graph.id(trivia);
+                self.trivia.borrow_mut().entry(id).or_insert_with(|| trivia.clone());
+                new_ctx.trivia = Trivia::Skip(id);
+            },
+            Mode::Syntactic(_) => (),
+            Mode::Lexical => new_ctx.trivia = Trivia::Lexical,
+        }
+        let expected = r.name().filter(|_| !ctx.in_not).map(|_| {
+            let e = self.expected.borrow();
+            (e.idx, e.items.len())
+        });
         
-        for p in &r.instr {
+        for p in r.instr() {
+            if let (Trivia::Skip(id), true) = (new_ctx.trivia, p.is_leaf()) {
+                new_ctx = match self.skip_trivia(id, new_ctx) {
+                    Progress::Some { steps: _, ctx } => ctx,
+                    progress => return progress,
+                };
+            }
+
+            let (idx, in_not) = (new_ctx.index, new_ctx.in_not);
+
             let progress = match *p {
                 // Leaves
                 Instr::AnyChar => self.scan_any_char_leaf(new_ctx),
-                Instr::AnyCharExcept(ref exclude) => self.scan_any_char_except_leaf(&exclude, new_ctx),
-                Instr::Alter(ref alter) => self.scan_alter_leaf(&alter, new_ctx),
-                Instr::AlterString(ref alter) => self.scan_alter_string_leaf(&alter, new_ctx),
+                Instr::AnyCharExcept(ref exclude) => self.scan_any_char_except_leaf(exclude, new_ctx),
+                Instr::Alter(ref alter) => self.scan_alter_leaf(alter, new_ctx),
+                Instr::AlterCi(ref alter) => self.scan_alter_ci_leaf(alter, new_ctx),
+                Instr::AlterString(ref alter) => self.scan_alter_string_leaf(alter, new_ctx),
+                Instr::CharClass(class) => self.scan_char_if_leaf(|c| class.contains(c), new_ctx),
+                Instr::CharIf(f) => self.scan_char_if_leaf(f, new_ctx),
                 Instr::CharIn(min, max) => self.scan_char_in_leaf(min, max, new_ctx),
+                Instr::CharSet(ref set) => self.scan_char_if_leaf(|c| set.contains(c), new_ctx),
                 Instr::Eof => self.scan_eof_leaf(new_ctx),
-                Instr::Literal(text) => self.scan_literal_leaf(&text, new_ctx),
-                Instr::LiteralString(ref text) => self.scan_literal_leaf(&text, new_ctx),
+                Instr::Literal(text) => self.scan_literal_leaf(text, new_ctx),
+                Instr::LiteralCi(ref text) => self.scan_literal_ci_leaf(text, new_ctx),
+                Instr::LiteralString(ref text) => self.scan_literal_leaf(text, new_ctx),
+                Instr::Token(ref kind) => self.scan_token_leaf(|e| e.kind() == *kind, new_ctx),
+                Instr::TokenIf(f) => self.scan_token_leaf(f, new_ctx),
                 
                 // Non leaves
+                Instr::And(ref r) => self.scan_and(r, new_ctx),
                 Instr::AnyOf(ref rules) => self.scan_any_of(rules, new_ctx),
                 Instr::Not(ref r) => self.scan_not(r, new_ctx),
+                Instr::Precedence(ref atom, ref ops) => self.scan_precedence(atom, ops, new_ctx),
                 Instr::Range(min, max, ref r) => self.scan_rule_range(min, max, r, new_ctx),
+                Instr::SepBy(min, max, ref item, ref sep, options) => self.scan_sep_by(min, max, item, sep, options, new_ctx),
                 
+                // A guard that rejects fails like a leaf, so the alternatives are still tried.
+                Instr::Guard(i) => {
+                    let pass = match r.guard(i) {
+                        GuardRef::Lexeme(f) => Ok(f(&new_ctx.lexeme, &new_ctx.branches)),
+                        GuardRef::State(f) => match self.state {
+                            Some(ref state) => state.borrow().read(|s| f(s, &new_ctx.lexeme, &new_ctx.branches)),
+                            None => Err(String::from("Rule has a state, scan it with `scan_with_state`.")),
+                        },
+                    };
+
+                    match pass {
+                        Ok(true) => Progress::Some { steps: 0, ctx: new_ctx },
+                        Ok(false) => Progress::No(new_ctx),
+                        Err(msg) => Progress::Error { idx: new_ctx.index, kind: RuleErrorKind::Semantic, msg },
+                    }
+                },
+
                 // No backtrack
                 Instr::NoBacktrack(ref err_msg) => {
-                    if new_ctx.in_not == false {
-                        let mut err = self.err.borrow_mut();
-                        *err = ScanErr { idx: new_ctx.index, msg: err_msg.clone() };
+                    if !new_ctx.in_and && !new_ctx.in_not {
+                        self.set_err(ScanErr { idx: new_ctx.index, kind: RuleErrorKind::Committed { label: err_msg.clone() }, msg: err_msg.clone() });
                     }
                     Progress::Some { steps: 0, ctx: new_ctx }
                 },
             };
 
+            if let Some(ref observer) = self.observer {
+                let leaf = p.expected();
+
+                if !leaf.is_empty() {
+                    let end = match progress {
+                        Progress::Some { ref ctx, .. } => Some(ctx.index),
+                        _ => None,
+                    };
+
+                    observer.borrow_mut().leaf(&leaf.join(" | "), idx, end);
+                }
+            }
+
             match progress {
                 Progress::Some { steps: _, ctx: newer_ctx } => new_ctx = newer_ctx,
-                Progress::No(_) => return self.no_or_error(ctx),
-                Progress::Error { idx, msg } => return Progress::Error { idx, msg },
+                Progress::No(_) => {
+                    if !in_not {
+                        self.expect(idx, p);
+                    }
+
+                    if let Some((name, expected)) = r.name().zip(expected) {
+                        self.expect_name(ctx.index, name, expected);
+                    }
+
+                    return self.no_or_error(ctx);
+                },
+                Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
             }
         }
         
-        ctx.merge_with(new_ctx, true, &r.branch_fn)
+        match r.branch_fn() {
+            Some(BranchRef::Lexeme(f)) => ctx.merge_with(new_ctx, Some(f)),
+            Some(BranchRef::Span(f)) => {
+                let span = self.span(&ctx, &new_ctx);
+                ctx.merge_with(new_ctx, Some(&|b, l: &str| f(b, l, span)))
+            },
+            Some(BranchRef::State(f)) => match self.state {
+                Some(ref state) => ctx.merge_with(new_ctx, Some(&|b, l: &str| state.borrow_mut().write(|s| f(s, b, l)))),
+                None => ctx.merge_with(new_ctx, Some(&|_, _: &str| Err(String::from("Rule has a state, scan it with `scan_with_state`.")))),
+            },
+            None => ctx.merge_with(new_ctx, None),
+        }
     }
     
-    fn scan_any_char_except_leaf<'s, T>(&self, exclude: &Vec<char>, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
-        let n = ctx.code_iter.next();
+    fn scan_any_char_except_leaf(&self, exclude: &[char], mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let n = ctx.next_char();
         
         if let Some(c) = n {
             if exclude.contains(&c) {
@@ -397,11 +1660,11 @@ impl Scanner {
         }
     }
     
-    fn scan_any_char_leaf<'s, T>(&self, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_any_char_leaf(&self, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let n = ctx.code_iter.next();
                 
-        if let Some(c) = n {
-            ctx.lexeme.push(c);
+        if let Some(e) = n {
+            e.push_lexeme(self.code, &mut ctx.lexeme);
             ctx.index += 1;
             Progress::Some { steps: 1, ctx }
         } 
@@ -410,11 +1673,11 @@ impl Scanner {
         }
     }
     
-    fn scan_alter_leaf<'s, T>(&self, list: &Vec<(&'static str, &'static str)>, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_alter_leaf(&self, list: &Vec<(&'static str, &'static str)>, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         for alter in list {
             let find = alter.0;
             let steps = find.chars().count();
-            let compare: String = ctx.code_iter.clone().take(steps).collect();
+            let compare: String = ctx.code_iter.clone().take(steps).map_while(|e| e.as_char()).collect();
 
             if find == compare {
                 ctx.code_iter.nth(steps - 1);
@@ -427,11 +1690,27 @@ impl Scanner {
         Progress::No(ctx)
     }
     
-    fn scan_alter_string_leaf<'s, T>(&self, list: &Vec<(String, String)>, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_alter_ci_leaf(&self, list: &Vec<(String, String)>, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        for alter in list {
+            let steps = alter.0.chars().count();
+            let compare: Vec<char> = ctx.code_iter.clone().take(steps).map_while(|e| e.as_char()).collect();
+
+            if compare.len() == steps && alter.0.chars().zip(compare).all(|(a, b)| fold_case(a) == fold_case(b)) {
+                ctx.code_iter.nth(steps - 1);
+                ctx.lexeme.push_str(&alter.1);
+                ctx.index += steps;
+                return Progress::Some { steps, ctx };
+            }
+        }
+
+        Progress::No(ctx)
+    }
+    
+    fn scan_alter_string_leaf(&self, list: &Vec<(String, String)>, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         for alter in list {
             let find = &alter.0;
             let steps = find.chars().count();
-            let compare: String = ctx.code_iter.clone().take(steps).collect();
+            let compare: String = ctx.code_iter.clone().take(steps).map_while(|e| e.as_char()).collect();
 
             if *find == compare {
                 ctx.code_iter.nth(steps - 1);
@@ -444,20 +1723,19 @@ impl Scanner {
         Progress::No(ctx)
     }
     
-    fn scan_any_of<'s, T>(&self, rules: &Vec<Rule<T>>, ctx: ScanCtx<'s, T>) -> Progress<'s,T> {
+    fn scan_any_of(&self, rules: &[G::Node], ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let (mut new_ctx, ctx) = ctx.branch();
         
         for r in rules {
             match self.run(r, new_ctx) {
                 Progress::Some { steps: _, ctx: new_ctx } => {
-                    let r = r.0.borrow();
-                    return ctx.merge_with(new_ctx, false, &r.branch_fn);
+                    return ctx.merge_with(new_ctx, None);
                 },
                 Progress::No(prev_new_ctx) => {
                     new_ctx = prev_new_ctx;
                 },
-                Progress::Error { idx, msg } => {
-                    return Progress::Error { idx, msg };
+                Progress::Error { idx, kind, msg } => {
+                    return Progress::Error { idx, kind, msg };
                 }
             }
         }
@@ -465,8 +1743,19 @@ impl Scanner {
         self.no_or_error(ctx)
     }
 
-    fn scan_char_in_leaf<'s, T>(&self, min: char, max: char, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
-        let c = ctx.code_iter.next();
+    fn scan_char_if_leaf(&self, f: impl Fn(char) -> bool, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        match ctx.next_char() {
+            Some(c) if f(c) => {
+                ctx.lexeme.push(c);
+                ctx.index += 1;
+                Progress::Some { steps: 1, ctx }
+            },
+            _ => Progress::No(ctx),
+        }
+    }
+
+    fn scan_char_in_leaf(&self, min: char, max: char, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let c = ctx.next_char();
 
         match c {
             Some(c) => {
@@ -485,8 +1774,8 @@ impl Scanner {
         }
     }
     
-    fn scan_eof_leaf<'s, T>(&self, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
-        if let None = ctx.code_iter.next() {
+    fn scan_eof_leaf(&self, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        if ctx.code_iter.next().is_none() {
             ctx.index += 1;
             Progress::Some { steps: 1, ctx }
         }
@@ -495,12 +1784,12 @@ impl Scanner {
         }
     }
     
-    fn scan_literal_leaf<'s, T>(&self, find: &str, mut ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_literal_leaf(&self, find: &str, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let iter = find.chars();
         let mut steps = 0;
             
         for i in iter {
-            let n = ctx.code_iter.next();
+            let n = ctx.next_char();
                 
             if let Some(c) = n {
                 if i != c {
@@ -519,18 +1808,176 @@ impl Scanner {
         Progress::Some { steps, ctx }
     }
     
-    fn scan_not<'s, T>(&self, rule: &Rule<T>, ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_literal_ci_leaf(&self, find: &str, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let mut steps = 0;
+            
+        for i in find.chars() {
+            match ctx.next_char() {
+                Some(c) if fold_case(i) == fold_case(c) => {
+                    ctx.lexeme.push(c);
+                    ctx.index += 1;
+                    steps += 1;
+                },
+                _ => return Progress::No(ctx),
+            }
+        }
+        
+        Progress::Some { steps, ctx }
+    }
+    
+    fn scan_token_leaf(&self, f: impl Fn(&I) -> bool, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        match ctx.code_iter.next() {
+            Some(e) if f(&e) => {
+                e.push_lexeme(self.code, &mut ctx.lexeme);
+                ctx.index += 1;
+                Progress::Some { steps: 1, ctx }
+            },
+            _ => Progress::No(ctx),
+        }
+    }
+    
+    // The state changes of a lookahead are undone, like its branches are dropped.
+    fn run_lookahead(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        self.push_state();
+        let progress = self.run(rule, ctx);
+        self.pop_state(true);
+        progress
+    }
+
+    fn scan_and(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let (mut new_ctx, ctx) = ctx.branch();
+        new_ctx.in_and = true;
+
+        match self.run_lookahead(rule, new_ctx) {
+            Progress::Some { steps: _, ctx: _ } => Progress::Some { steps: 0, ctx },
+            Progress::No(_) => Progress::No(ctx),
+            Progress::Error { .. } => Progress::No(ctx),
+        }
+    }
+
+    fn scan_not(&self, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let (mut new_ctx, ctx) = ctx.branch();
         new_ctx.in_not = true;
 
-        match self.run(rule, new_ctx) {
+        match self.run_lookahead(rule, new_ctx) {
             Progress::Some { steps: _, ctx: _ } => Progress::No(ctx),
             Progress::No(_) => Progress::Some { steps: 0, ctx },
-            Progress::Error{ idx: _, msg: _ } => Progress::Some { steps: 0, ctx },
+            Progress::Error { .. } => Progress::Some { steps: 0, ctx },
         }
     }
     
-    fn scan_rule_range<'s, T>(&self, min: u64, max: u64, rule: &Rule<T>, ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_precedence(&self, atom: &G::Node, ops: &[Operator<G::Node>], ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let (new_ctx, ctx) = ctx.branch();
+
+        match self.scan_operation(atom, ops, 0, new_ctx) {
+            Progress::Some { steps: _, ctx: new_ctx } => ctx.merge_with(new_ctx, None),
+            Progress::No(_) => self.no_or_error(ctx),
+            Progress::Error { idx, kind, msg } => Progress::Error { idx, kind, msg },
+        }
+    }
+
+    // Scans an operand followed by the operators which bind at least with `min_power`.
+    fn scan_operation(&self, atom: &G::Node, ops: &[Operator<G::Node>], min_power: u64, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let mut lhs = match self.scan_operand(atom, ops, ctx) {
+            Progress::Some { steps: _, ctx } => ctx,
+            progress => return progress,
+        };
+
+        'operators: loop {
+            for op in ops {
+                match op.left_power() {
+                    Some(power) if power >= min_power => (),
+                    _ => continue,
+                }
+
+                let op_ctx = match self.run(&op.op, lhs.fork()) {
+                    Progress::Some { steps: _, ctx } => ctx,
+                    Progress::No(_) => continue,
+                    Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+                };
+
+                let idx = lhs.index;
+                let text = op_ctx.lexeme.clone();
+
+                if op.fixity == Fixity::Postfix {
+                    lhs.advance(op_ctx, false);
+                }
+                else {
+                    match self.scan_operation(atom, ops, op.right_power(), op_ctx.fork()) {
+                        Progress::Some { steps: _, ctx: rhs } => {
+                            lhs.advance(op_ctx, false);
+                            lhs.advance(rhs, true);
+                        },
+                        Progress::No(_) => continue,
+                        Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+                    }
+                }
+
+                lhs = match self.fold(&op.fold, idx, &text, lhs) {
+                    Progress::Some { steps: _, ctx } => ctx,
+                    progress => return progress,
+                };
+
+                continue 'operators;
+            }
+
+            break;
+        }
+
+        Progress::Some { steps: 0, ctx: lhs }
+    }
+
+    // A prefix operator with its operand or else the atom.
+    fn scan_operand(&self, atom: &G::Node, ops: &[Operator<G::Node>], ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        for op in ops.iter().filter(|op| op.fixity == Fixity::Prefix) {
+            let op_ctx = match self.run(&op.op, ctx.fork()) {
+                Progress::Some { steps: _, ctx } => ctx,
+                Progress::No(_) => continue,
+                Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+            };
+
+            match self.scan_operation(atom, ops, op.right_power(), op_ctx.fork()) {
+                Progress::Some { steps: _, ctx: rhs } => {
+                    let mut new_ctx = ctx.fork();
+                    let text = op_ctx.lexeme.clone();
+                    new_ctx.advance(op_ctx, false);
+                    new_ctx.advance(rhs, true);
+                    return self.fold(&op.fold, ctx.index, &text, new_ctx);
+                },
+                Progress::No(_) => continue,
+                Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+            }
+        }
+
+        match self.run(atom, ctx.fork()) {
+            Progress::Some { steps: _, ctx: operand } => {
+                let mut new_ctx = ctx.fork();
+                new_ctx.advance(operand, true);
+                Progress::Some { steps: 0, ctx: new_ctx }
+            },
+            Progress::No(_) => Progress::No(ctx),
+            Progress::Error { idx, kind, msg } => Progress::Error { idx, kind, msg },
+        }
+    }
+
+    fn fold(&self, fold: &G::Node, idx: usize, op: &str, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let branches = std::mem::take(&mut ctx.branches);
+
+        let result = match self.graph.body(fold).branch_fn() {
+            Some(BranchRef::Lexeme(f)) => f(branches, op),
+            _ => unreachable!(),
+        };
+
+        match result {
+            Ok(val) => {
+                ctx.branches.push(val);
+                Progress::Some { steps: 0, ctx }
+            },
+            Err(msg) => Progress::Error { idx, kind: RuleErrorKind::Semantic, msg },
+        }
+    }
+
+    fn scan_rule_range(&self, min: u64, max: u64, rule: &G::Node, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let (mut new_ctx, ctx) = ctx.branch();
         let mut count = 0u64;
         
@@ -538,8 +1985,7 @@ impl Scanner {
             match self.run(rule, new_ctx) {
                 Progress::Some { steps, ctx: newer_ctx } => {
                     if steps == 0 {
-                        let r = rule.0.borrow();
-                        return ctx.merge_with(newer_ctx, false, &r.branch_fn);
+                        return ctx.merge_with(newer_ctx, None);
                     }
 
                     new_ctx = newer_ctx;
@@ -553,26 +1999,213 @@ impl Scanner {
                     new_ctx = prev_new_ctx;
                     break;
                 },
-                Progress::Error { idx, msg } => {
-                    return Progress::Error { idx, msg };
+                Progress::Error { idx, kind, msg } => {
+                    return Progress::Error { idx, kind, msg };
                 }
             }
         }
         
         if count >= min && count <= max {
-            let r = rule.0.borrow();
-            ctx.merge_with(new_ctx, false, &r.branch_fn)
+            ctx.merge_with(new_ctx, None)
         }
         else {
             self.no_or_error(ctx)
         }
     }
     
-    fn no_or_error<'s, T>(&self, ctx: ScanCtx<'s, T>) -> Progress<'s, T> {
+    fn scan_sep_by(&self, min: u64, max: u64, item: &G::Node, sep: &G::Node, options: SepOptions, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let (mut new_ctx, ctx) = ctx.branch();
+        let mut count = 0u64;
+
+        loop {
+            if count == max && !options.trailing {
+                break;
+            }
+
+            // Every item after the first one comes after a separator.
+            let sep_ctx = if count == 0 {
+                None
+            }
+            else {
+                match self.run(sep, new_ctx.fork()) {
+                    Progress::Some { steps: _, ctx: sep_ctx } => Some(sep_ctx),
+                    Progress::No(_) => break,
+                    Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+                }
+            };
+
+            let progress = if count == max {
+                None
+            }
+            else {
+                Some(self.run(item, sep_ctx.as_ref().unwrap_or(&new_ctx).fork()))
+            };
+
+            match progress {
+                Some(Progress::Some { steps: _, ctx: item_ctx }) => {
+                    let start = new_ctx.index;
+
+                    if let Some(sep_ctx) = sep_ctx {
+                        new_ctx.advance(sep_ctx, options.keep_sep);
+                    }
+
+                    new_ctx.advance(item_ctx, true);
+                    count += 1;
+
+                    if new_ctx.index == start {
+                        break;
+                    }
+                },
+                Some(Progress::Error { idx, kind, msg }) => return Progress::Error { idx, kind, msg },
+                _ => {
+                    if let Some(sep_ctx) = sep_ctx.filter(|_| options.trailing) {
+                        new_ctx.advance(sep_ctx, options.keep_sep);
+                    }
+
+                    break;
+                },
+            }
+        }
+
+        if count >= min {
+            ctx.merge_with(new_ctx, None)
+        }
+        else {
+            self.no_or_error(ctx)
+        }
+    }
+
+    // Skips trivia without adding it to the lexeme. The trivia rule is scanned as a lexical rule and what it 
+    // expected isn't reported.
+    fn skip_trivia(&self, id: usize, mut ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
+        let trivia = self.trivia.borrow()[&id].clone();
+        let expected = self.expected.take();
+
+        loop {
+            let mut trivia_ctx = ctx.fork();
+            trivia_ctx.trivia = Trivia::Lexical;
+
+            match self.run(&trivia, trivia_ctx) {
+                Progress::Some { steps: _, ctx: trivia_ctx } if trivia_ctx.index > ctx.index => {
+                    ctx.code_iter = trivia_ctx.code_iter;
+                    ctx.index = trivia_ctx.index;
+                },
+                Progress::Error { idx, kind, msg } => return Progress::Error { idx, kind, msg },
+                _ => break,
+            }
+        }
+
+        self.expected.replace(expected);
+        Progress::Some { steps: 0, ctx }
+    }
+
+    fn error(&self, idx: usize, kind: RuleErrorKind, msg: String) -> RuleError {
+        self.token_error(idx, RuleError { kind, ..RuleError::new(self.code, self.char_index(idx), msg) })
+    }
+
+    fn expected_error(&self, idx: usize, kind: RuleErrorKind, items: Vec<String>) -> RuleError {
+        self.token_error(idx, RuleError { kind, ..RuleError::expected(self.code, self.char_index(idx), items) })
+    }
+
+    // An error in a token stream is at a token, it ends where the token ends.
+    fn token_error(&self, idx: usize, err: RuleError) -> RuleError {
+        let end = match self.token_spans {
+            Some(ref spans) => spans.get(idx).map(|span| (span.end, self.code[..span.end].chars().count())),
+            None => return err,
+        };
+
+        RuleError { end, token: Some(idx), ..err }
+    }
+
+    // The char index in the source, a token is at the char where it starts.
+    fn char_index(&self, idx: usize) -> usize {
+        match self.token_spans {
+            Some(_) => self.code[..self.token_byte(idx)].chars().count(),
+            None => idx,
+        }
+    }
+
+    // The byte index in the source where a token starts, after the last token that's where it ends.
+    fn token_byte(&self, idx: usize) -> usize {
+        let spans = self.token_spans.as_deref().unwrap_or_default();
+
+        match spans.get(idx) {
+            Some(span) => span.start,
+            None => spans.last().map_or(0, |span| span.end),
+        }
+    }
+
+    fn span(&self, start: &ScanCtx<'s, T, I>, end: &ScanCtx<'s, T, I>) -> Span {
+        let (start_byte, end_byte) = match (I::offset(self.code, &start.code_iter), I::offset(self.code, &end.code_iter)) {
+            (Some(start_byte), Some(end_byte)) => (start_byte, end_byte),
+            _ => {
+                let start_byte = self.token_byte(start.index);
+
+                match self.token_spans {
+                    Some(ref spans) if end.index > start.index => (start_byte, spans[end.index - 1].end),
+                    _ => (start_byte, start_byte),
+                }
+            },
+        };
+        let (start_char, line, col) = self.position(start_byte);
+        let (end_char, _, _) = self.position(end_byte);
+        Span { col, end_byte, end_char, line, start_byte, start_char }
+    }
+
+    // The char index, line and column of a byte index. The byte and char index of every line start are 
+    // collected the first time a span is needed.
+    fn position(&self, byte_idx: usize) -> (usize, usize, usize) {
+        let lines = self.lines.get_or_init(|| {
+            let mut lines = vec![(0, 0)];
+            let mut chars = self.code.char_indices().enumerate().peekable();
+
+            while let Some((char_idx, (i, c))) = chars.next() {
+                let new_line = match c {
+                    '\n' => true,
+                    '\r' => chars.peek().is_none_or(|(_, (_, n))| *n != '\n'),
+                    _ => false,
+                };
+
+                if new_line {
+                    lines.push((i + 1, char_idx + 1));
+                }
+            }
+
+            lines
+        });
+
+        let line = lines.partition_point(|&(b, _)| b <= byte_idx);
+        let (line_byte, line_char) = lines[line - 1];
+        let col = self.code[line_byte..byte_idx].chars().count();
+        (line_char + col, line, col)
+    }
+
+    fn push_state(&self) {
+        if let Some(ref state) = self.state {
+            state.borrow_mut().push();
+        }
+    }
+
+    fn pop_state(&self, rollback: bool) {
+        if let Some(ref state) = self.state {
+            state.borrow_mut().pop(rollback);
+        }
+    }
+
+    fn state_writes(&self) -> usize {
+        self.state.as_ref().map_or(0, |state| state.borrow().writes())
+    }
+
+    fn set_err(&self, err: ScanErr) {
+        *self.err.borrow_mut() = err;
+        self.err_writes.set(self.err_writes.get() + 1);
+    }
+
+    fn no_or_error(&self, ctx: ScanCtx<'s, T, I>) -> Progress<'s, T, I> {
         let err = self.err.borrow();
 
         if ctx.index < err.idx {
-            Progress::Error { idx: err.idx, msg: err.msg.clone() }
+            Progress::Error { idx: err.idx, kind: err.kind.clone(), msg: err.msg.clone() }
         }
         else {
             Progress::No(ctx)
@@ -580,6 +2213,24 @@ impl Scanner {
     }
 }
 
+// Every string to find must have a character, a replacement can be empty.
+fn check_alter<S: AsRef<str>>(list: &[(S, S)]) -> Result<(), GrammarError> {
+    if list.is_empty() {
+        return Err(GrammarError::Invalid(String::from("List is empty.")));
+    }
+
+    if list.iter().any(|t| t.0.as_ref().is_empty()) {
+        return Err(GrammarError::Invalid(String::from("The strings to find must be minimal one character long.")));
+    }
+
+    Ok(())
+}
+
+// The panicking builder methods are the `try_` ones with the error as panic message.
+fn or_panic<R>(result: Result<R, GrammarError>) -> R {
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
 struct CursorPos {
     col: usize,
     line: usize,
@@ -617,7 +2268,7 @@ fn cursor_pos(text: &str) -> CursorPos {
     line_counter.none_or_many(&line);
 
     if let Ok(lines) = line_counter.scan(text) {
-        if lines.len() == 0 {
+        if lines.is_empty() {
             // The scanned `text` is an empty string.
             CursorPos { col: 0, line: 1 }
         }
@@ -0,0 +1,146 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// Builds an operator precedence rule. Operands are scanned with the atom rule, operators are folded
+// Pratt style: an operator with a higher power binds stronger. Every operand must give exactly one
+// branch, the fold closures get those values together with the text of the operator.
+
+use super::{or_panic, GrammarError, Instr, Rule};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fixity {
+    Infix(Assoc),
+    Postfix,
+    Prefix,
+}
+
+pub(crate) struct Operator<N> {
+    pub(crate) fixity: Fixity,
+    pub(crate) fold: N,
+    pub(crate) op: N,
+    pub(crate) power: u32,
+}
+
+pub struct PrecedenceBuilder<T> {
+    atom: Rule<T>,
+    ops: Vec<(usize, Operator<Rule<T>>)>,
+}
+
+impl<N> Operator<N> {
+    // The binding powers to the left and right of the operator. The weaker side of an infix operator
+    // decides the associativity, prefix operators have nothing on their left and postfix operators
+    // nothing on their right.
+    pub(crate) fn left_power(&self) -> Option<u64> {
+        let power = self.power as u64 * 2;
+
+        match self.fixity {
+            Fixity::Infix(Assoc::Left) | Fixity::Postfix => Some(power),
+            Fixity::Infix(Assoc::Right) => Some(power + 1),
+            Fixity::Prefix => None,
+        }
+    }
+
+    pub(crate) fn right_power(&self) -> u64 {
+        let power = self.power as u64 * 2;
+
+        match self.fixity {
+            Fixity::Infix(Assoc::Left) | Fixity::Prefix | Fixity::Postfix => power + 1,
+            Fixity::Infix(Assoc::Right) => power,
+        }
+    }
+}
+
+impl<T> PrecedenceBuilder<T> {
+    pub fn new(atom: &Rule<T>) -> Self {
+        Self { atom: atom.clone(), ops: Vec::new() }
+    }
+
+    pub fn infix(self, op: &str, power: u32, assoc: Assoc, fold: impl Fn(T, &str, T) -> Result<T, String> + 'static) -> Self {
+        self.push(op, power, Fixity::Infix(assoc), Rule::new(move |b, op| infix_fold(&fold, b, op)))
+    }
+
+    pub fn infix_sync(self, op: &str, power: u32, assoc: Assoc, fold: impl Fn(T, &str, T) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        self.push(op, power, Fixity::Infix(assoc), Rule::new_sync(move |b, op| infix_fold(&fold, b, op)))
+    }
+
+    pub fn postfix(self, op: &str, power: u32, fold: impl Fn(T, &str) -> Result<T, String> + 'static) -> Self {
+        self.push(op, power, Fixity::Postfix, Rule::new(move |b, op| postfix_fold(&fold, b, op)))
+    }
+
+    pub fn postfix_sync(self, op: &str, power: u32, fold: impl Fn(T, &str) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        self.push(op, power, Fixity::Postfix, Rule::new_sync(move |b, op| postfix_fold(&fold, b, op)))
+    }
+
+    pub fn prefix(self, op: &str, power: u32, fold: impl Fn(&str, T) -> Result<T, String> + 'static) -> Self {
+        self.push(op, power, Fixity::Prefix, Rule::new(move |b, op| prefix_fold(&fold, b, op)))
+    }
+
+    pub fn prefix_sync(self, op: &str, power: u32, fold: impl Fn(&str, T) -> Result<T, String> + Send + Sync + 'static) -> Self {
+        self.push(op, power, Fixity::Prefix, Rule::new_sync(move |b, op| prefix_fold(&fold, b, op)))
+    }
+
+    pub fn build(self) -> Rule<T> {
+        or_panic(self.try_build())
+    }
+
+    pub fn try_build(mut self) -> Result<Rule<T>, GrammarError> {
+        if self.ops.iter().any(|&(len, _)| len == 0) {
+            return Err(GrammarError::Invalid(String::from("Operator is empty.")));
+        }
+
+        // The longest operator is tried first, so "**" wins from "*".
+        self.ops.sort_by_key(|&(len, _)| std::cmp::Reverse(len));
+
+        let rule = Rule::default();
+        rule.0.borrow_mut().instr.push(Instr::Precedence(self.atom, self.ops.into_iter().map(|(_, op)| op).collect()));
+        Ok(rule)
+    }
+
+    fn push(mut self, op: &str, power: u32, fixity: Fixity, fold: Rule<T>) -> Self {
+        // An empty operator is rejected by `try_build`.
+        let rule = Rule::default();
+        let _ = rule.try_literal_string(op.to_string());
+
+        self.ops.push((op.chars().count(), Operator { fixity, fold, op: rule, power }));
+        self
+    }
+}
+
+// The branch functions of the operators fold the operands, after checking there's one per side.
+fn infix_fold<T>(fold: &impl Fn(T, &str, T) -> Result<T, String>, b: Vec<T>, op: &str) -> Result<T, String> {
+    let mut b = b.into_iter();
+
+    match (b.next(), b.next(), b.next()) {
+        (Some(lhs), Some(rhs), None) => fold(lhs, op, rhs),
+        _ => Err(operand_error(op)),
+    }
+}
+
+fn postfix_fold<T>(fold: &impl Fn(T, &str) -> Result<T, String>, b: Vec<T>, op: &str) -> Result<T, String> {
+    let mut b = b.into_iter();
+
+    match (b.next(), b.next()) {
+        (Some(lhs), None) => fold(lhs, op),
+        _ => Err(operand_error(op)),
+    }
+}
+
+fn prefix_fold<T>(fold: &impl Fn(&str, T) -> Result<T, String>, b: Vec<T>, op: &str) -> Result<T, String> {
+    let mut b = b.into_iter();
+
+    match (b.next(), b.next()) {
+        (Some(rhs), None) => fold(op, rhs),
+        _ => Err(operand_error(op)),
+    }
+}
+
+fn operand_error(op: &str) -> String {
+    format!("Operands of \"{}\" must have exactly one branch.", op)
+}
@@ -0,0 +1,186 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::marker::PhantomData;
+use super::{FrozenRule, Graph, Progress, Rule, RuleError, RuleErrorKind, RuleGraph, ScanCtx, ScanErr, Scanner};
+
+pub struct Match<'s, T> {
+    pub branches: Vec<T>,
+    pub char_end: usize,
+    pub char_start: usize,
+    pub end: usize,
+    pub start: usize,
+    pub text: &'s str,
+}
+
+pub struct Matches<'r, 's, T> {
+    char_pos: usize,
+    code: &'s str,
+    pos: Option<usize>,
+    root: Root<'r, T>,
+}
+
+pub struct Split<'r, 's, T> {
+    matches: Matches<'r, 's, T>,
+    pos: Option<usize>,
+}
+
+enum Root<'r, T> {
+    Frozen(&'r FrozenRule<T>),
+    Rule(&'r Rule<T>),
+}
+
+impl<T> Rule<T> {
+    // An error raised by a branch function at a position is a failed match there, the search goes on at
+    // the next position. Use `try_find` to stop at the error instead.
+    pub fn find<'s>(&self, code: &'s str) -> Option<Match<'s, T>> {
+        find_at(&RuleGraph(PhantomData), self, code, 0, 0, false).unwrap_or(None)
+    }
+
+    pub fn try_find<'s>(&self, code: &'s str) -> Result<Option<Match<'s, T>>, RuleError> {
+        find_at(&RuleGraph(PhantomData), self, code, 0, 0, true)
+    }
+
+    pub fn find_iter<'r, 's>(&'r self, code: &'s str) -> Matches<'r, 's, T> {
+        Matches::new(Root::Rule(self), code)
+    }
+
+    pub fn replace_all(&self, code: &str, replace: impl FnMut(Match<T>) -> String) -> String {
+        replace_all(self.find_iter(code), replace)
+    }
+
+    pub fn split<'r, 's>(&'r self, code: &'s str) -> Split<'r, 's, T> {
+        Split { matches: self.find_iter(code), pos: Some(0) }
+    }
+}
+
+impl<T> FrozenRule<T> {
+    // An error raised by a branch function at a position is a failed match there, the search goes on at
+    // the next position. Use `try_find` to stop at the error instead.
+    pub fn find<'s>(&self, code: &'s str) -> Option<Match<'s, T>> {
+        find_at(self, &0, code, 0, 0, false).unwrap_or(None)
+    }
+
+    pub fn try_find<'s>(&self, code: &'s str) -> Result<Option<Match<'s, T>>, RuleError> {
+        find_at(self, &0, code, 0, 0, true)
+    }
+
+    pub fn find_iter<'r, 's>(&'r self, code: &'s str) -> Matches<'r, 's, T> {
+        Matches::new(Root::Frozen(self), code)
+    }
+
+    pub fn replace_all(&self, code: &str, replace: impl FnMut(Match<T>) -> String) -> String {
+        replace_all(self.find_iter(code), replace)
+    }
+
+    pub fn split<'r, 's>(&'r self, code: &'s str) -> Split<'r, 's, T> {
+        Split { matches: self.find_iter(code), pos: Some(0) }
+    }
+}
+
+impl<'r, 's, T> Matches<'r, 's, T> {
+    fn new(root: Root<'r, T>, code: &'s str) -> Self {
+        Self { char_pos: 0, code, pos: Some(0), root }
+    }
+}
+
+impl<'r, 's, T> Iterator for Matches<'r, 's, T> {
+    type Item = Match<'s, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos?;
+
+        let m = match self.root {
+            Root::Frozen(frozen) => find_at(frozen, &0, self.code, pos, self.char_pos, false).unwrap_or(None),
+            Root::Rule(rule) => find_at(&RuleGraph(PhantomData), rule, self.code, pos, self.char_pos, false).unwrap_or(None),
+        };
+
+        match m {
+            // After an empty match the search continues at the next character.
+            Some(ref m) if m.start == m.end => {
+                match self.code[m.end..].chars().next() {
+                    Some(c) => {
+                        self.pos = Some(m.end + c.len_utf8());
+                        self.char_pos = m.char_end + 1;
+                    },
+                    None => self.pos = None,
+                }
+            },
+            Some(ref m) => {
+                self.pos = Some(m.end);
+                self.char_pos = m.char_end;
+            },
+            None => self.pos = None,
+        }
+
+        m
+    }
+}
+
+impl<'r, 's, T> Iterator for Split<'r, 's, T> {
+    type Item = &'s str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos?;
+        let code = self.matches.code;
+
+        for m in self.matches.by_ref() {
+            // An empty match at the start or the end of the text doesn't split.
+            if m.start == m.end && (m.start == 0 || m.start == code.len()) {
+                continue;
+            }
+
+            self.pos = Some(m.end);
+            return Some(&code[pos..m.start]);
+        }
+
+        self.pos = None;
+        Some(&code[pos..])
+    }
+}
+
+// Finds the first match from `start` on. An error raised at a position is returned when `stop` is set,
+// otherwise it's skipped like a failed match.
+fn find_at<'s, T, G: Graph<T, char>>(graph: &G, root: &G::Node, code: &'s str, start: usize, char_start: usize, stop: bool) -> Result<Option<Match<'s, T>>, RuleError> {
+    let scanner = Scanner::new(graph, code);
+    let positions = code[start..].char_indices().map(|(i, _)| start + i).chain(Some(code.len()));
+
+    for (char_idx, byte_idx) in (char_start..).zip(positions) {
+        *scanner.err.borrow_mut() = ScanErr { idx: 0, kind: RuleErrorKind::Syntax, msg: String::from("Syntax error.") };
+
+        match scanner.run(root, ScanCtx::at(code, byte_idx, char_idx)) {
+            Progress::Some { ctx, .. } => {
+                let end = code.len() - ctx.code_iter.as_str().len();
+
+                return Ok(Some(Match {
+                    branches: ctx.branches,
+                    char_end: char_idx + code[byte_idx..end].chars().count(),
+                    char_start: char_idx,
+                    end,
+                    start: byte_idx,
+                    text: &code[byte_idx..end],
+                }));
+            },
+            Progress::Error { idx, kind, msg } if stop => return Err(scanner.error(idx, kind, msg)),
+            _ => {},
+        }
+    }
+
+    Ok(None)
+}
+
+fn replace_all<T>(matches: Matches<T>, mut replace: impl FnMut(Match<T>) -> String) -> String {
+    let code = matches.code;
+    let mut result = String::with_capacity(code.len());
+    let mut pos = 0;
+
+    for m in matches {
+        result.push_str(&code[pos..m.start]);
+        pos = m.end;
+        result.push_str(&replace(m));
+    }
+
+    result.push_str(&code[pos..]);
+    result
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// The user state of `Rule::scan_with_state`. Every scanned rule is a point the state can be rolled back
+// to when the rule fails. The state is only cloned when it's changed after a point, all points since the
+// last change share that clone.
+
+use std::any::Any;
+use std::rc::Rc;
+
+pub(crate) struct State<'a> {
+    points: Vec<Option<Rc<dyn Any>>>,
+    restore: fn(&mut dyn Any, &dyn Any),
+    snapshot: fn(&dyn Any) -> Rc<dyn Any>,
+    value: &'a mut dyn Any,
+    writes: usize,
+}
+
+impl<'a> State<'a> {
+    pub(crate) fn new<S: Clone + 'static>(value: &'a mut S) -> Self {
+        Self {
+            points: Vec::new(),
+            restore: |value, snapshot| {
+                if let (Some(value), Some(snapshot)) = (value.downcast_mut::<S>(), snapshot.downcast_ref::<S>()) {
+                    value.clone_from(snapshot);
+                }
+            },
+            snapshot: |value| match value.downcast_ref::<S>() {
+                Some(value) => Rc::new(value.clone()),
+                None => unreachable!(),
+            },
+            value,
+            writes: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self) {
+        self.points.push(None);
+    }
+
+    pub(crate) fn pop(&mut self, rollback: bool) {
+        if let Some(Some(snapshot)) = self.points.pop().filter(|_| rollback) {
+            (self.restore)(self.value, snapshot.as_ref());
+        }
+    }
+
+    // Rolls back the changes since the last point without removing it. The changes are returned, so
+    // they can be put back with `redo`.
+    pub(crate) fn undo(&mut self) -> Option<Rc<dyn Any>> {
+        let snapshot = self.points.last()?.clone()?;
+        let changed = (self.snapshot)(self.value);
+        (self.restore)(self.value, snapshot.as_ref());
+        Some(changed)
+    }
+
+    pub(crate) fn redo(&mut self, changed: Option<Rc<dyn Any>>) {
+        if let Some(changed) = changed {
+            (self.restore)(self.value, changed.as_ref());
+        }
+    }
+
+    // The number of times the state was handed out to be changed.
+    pub(crate) fn writes(&self) -> usize {
+        self.writes
+    }
+
+    pub(crate) fn read<R>(&self, f: impl FnOnce(&dyn Any) -> R) -> R {
+        f(self.value)
+    }
+
+    pub(crate) fn write<R>(&mut self, f: impl FnOnce(&mut dyn Any) -> R) -> R {
+        if let Some(None) = self.points.last() {
+            let snapshot = (self.snapshot)(self.value);
+
+            for point in self.points.iter_mut().rev().take_while(|p| p.is_none()) {
+                *point = Some(snapshot.clone());
+            }
+        }
+
+        self.writes += 1;
+        f(self.value)
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+use std::io::{self, Write};
+
+// Gets called by `scan_traced` while scanning. The indices are char indices, a failing leaf has no end.
+pub trait ScanObserver {
+    fn enter(&mut self, _rule: &RuleInfo, _index: usize) {}
+    fn success(&mut self, _rule: &RuleInfo, _start: usize, _end: usize) {}
+    fn fail(&mut self, _rule: &RuleInfo, _index: usize) {}
+    fn leaf(&mut self, _leaf: &str, _start: usize, _end: Option<usize>) {}
+}
+
+pub struct RuleInfo<'a> {
+    pub id: usize,
+    pub name: Option<&'a str>,
+}
+
+// Writes an indented trace of the named rules and the leaves scanned by them. Unnamed rules are left 
+// out, they're usually just glue between the named ones.
+pub struct TracePrinter<W: Write> {
+    depth: usize,
+    out: W,
+}
+
+impl Default for TracePrinter<io::Stderr> {
+    fn default() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl<W: Write> TracePrinter<W> {
+    pub fn new(out: W) -> Self {
+        Self { depth: 0, out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn line(&mut self, text: String) {
+        // Tracing is for debugging, a failing writer shouldn't fail the scan.
+        let _ = writeln!(self.out, "{:indent$}{}", "", text, indent = self.depth * 2);
+    }
+}
+
+impl<W: Write> ScanObserver for TracePrinter<W> {
+    fn enter(&mut self, rule: &RuleInfo, index: usize) {
+        if let Some(name) = rule.name {
+            self.line(format!("{} @{}", name, index));
+            self.depth += 1;
+        }
+    }
+
+    fn success(&mut self, rule: &RuleInfo, start: usize, end: usize) {
+        if let Some(name) = rule.name {
+            self.depth -= 1;
+            self.line(format!("{} matched @{}..{}", name, start, end));
+        }
+    }
+
+    fn fail(&mut self, rule: &RuleInfo, index: usize) {
+        if let Some(name) = rule.name {
+            self.depth -= 1;
+            self.line(format!("{} failed @{}", name, index));
+        }
+    }
+
+    fn leaf(&mut self, leaf: &str, start: usize, end: Option<usize>) {
+        match end {
+            Some(end) => self.line(format!("{} matched @{}..{}", leaf, start, end)),
+            None => self.line(format!("{} failed @{}", leaf, start)),
+        }
+    }
+}
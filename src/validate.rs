@@ -0,0 +1,287 @@
+// Copyright (c) 2015-2020 Vincent van Ingen <code@abitvin.com>
+// Licensed under the MIT license <LICENSE.md or http://opensource.org/licenses/MIT>
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// Finds problems in a rule graph without scanning anything. A rule is nullable when it can match
+// without consuming input, and infallible when it always matches. Both are worked out for all rules at
+// once until nothing changes anymore.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use super::{Element, Instr, Mode, Rule};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GrammarIssue {
+    // A rule without instructions. Scanning it as the root panics, anywhere else it matches without
+    // consuming input.
+    EmptyRule(String),
+    // A rule which can enter itself without consuming input. It scans, but the seed is grown by
+    // scanning the rules involved over and over again.
+    LeftRecursion(String),
+    // A repetition of a rule which can match without consuming input, it stops after the first empty
+    // match.
+    NullableRepetition(String),
+    // An alternative of `any_of` which is never tried, an earlier one always matches in its place.
+    UnreachableAlternative { rule: String, alternative: usize, shadowed_by: usize },
+}
+
+impl fmt::Display for GrammarIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GrammarIssue::EmptyRule(ref rule) => write!(f, "Rule is not defined: {}", rule),
+            GrammarIssue::LeftRecursion(ref rule) => write!(f, "Rule is left recursive: {}", rule),
+            GrammarIssue::NullableRepetition(ref rule) => write!(f, "Repeated rule can match nothing in: {}", rule),
+            GrammarIssue::UnreachableAlternative { ref rule, alternative, shadowed_by } => {
+                write!(f, "Alternative {} is shadowed by alternative {} in: {}", alternative, shadowed_by, rule)
+            },
+        }
+    }
+}
+
+pub(crate) fn validate<T, I: Element>(root: &Rule<T, I>) -> Vec<GrammarIssue> {
+    let rules = collect(root);
+    let nullable = nullable(&rules);
+    let infallible = infallible(&rules);
+    let is_nullable = |rule: &Rule<T, I>| nullable.contains(&rule.id());
+    let mut issues = Vec::new();
+
+    for rule in &rules {
+        let r = rule.0.borrow();
+
+        if r.instr.is_empty() {
+            issues.push(GrammarIssue::EmptyRule(describe(rule)));
+            continue;
+        }
+
+        for instr in &r.instr {
+            match *instr {
+                Instr::AnyOf(ref alts) => {
+                    for (alternative, alt) in alts.iter().enumerate() {
+                        let shadowed_by = alts[..alternative].iter().position(|prev| {
+                            if infallible.contains(&prev.id()) {
+                                return true;
+                            }
+
+                            match (literal_prefix(prev), literal_prefix(alt)) {
+                                ((prefix, true), (text, _)) => text.starts_with(&prefix),
+                                _ => false,
+                            }
+                        });
+
+                        if let Some(shadowed_by) = shadowed_by {
+                            issues.push(GrammarIssue::UnreachableAlternative { rule: describe(rule), alternative, shadowed_by });
+                        }
+                    }
+                },
+                Instr::Range(_, max, ref item) if max > 1 && is_nullable(item) => {
+                    issues.push(GrammarIssue::NullableRepetition(describe(rule)));
+                },
+                Instr::SepBy(_, max, ref item, ref sep, _) if max > 1 && is_nullable(item) && is_nullable(sep) => {
+                    issues.push(GrammarIssue::NullableRepetition(describe(rule)));
+                },
+                _ => (),
+            }
+        }
+    }
+
+    // A left recursive cycle is reported once, at the first rule of the cycle found from the root.
+    let left = left_edges(&rules, &nullable);
+    let mut reported = HashSet::new();
+
+    for rule in &rules {
+        let id = rule.id();
+        let reach = reachable(&left, id);
+
+        if reach.contains(&id) && !reported.contains(&id) {
+            issues.push(GrammarIssue::LeftRecursion(describe(rule)));
+            reported.extend(reach.into_iter().filter(|&other| reachable(&left, other).contains(&id)));
+        }
+    }
+
+    issues
+}
+
+// All rules reachable from the root, the root first.
+pub(crate) fn collect<T, I: Element>(root: &Rule<T, I>) -> Vec<Rule<T, I>> {
+    let mut ids = HashSet::new();
+    let mut rules = vec![root.clone()];
+    let mut i = 0;
+    ids.insert(root.id());
+
+    while i < rules.len() {
+        let mut children = Vec::new();
+
+        {
+            let r = rules[i].0.borrow();
+
+            for instr in &r.instr {
+                instr.map(|rule| children.push(rule.clone()));
+            }
+
+            children.extend(r.recover.clone());
+
+            if let Mode::Syntactic(ref trivia) = r.mode {
+                children.push(trivia.clone());
+            }
+        }
+
+        for child in children {
+            if ids.insert(child.id()) {
+                rules.push(child);
+            }
+        }
+
+        i += 1;
+    }
+
+    rules
+}
+
+// An empty rule is nullable, that's how it's scanned when it isn't the root.
+fn nullable<T, I: Element>(rules: &[Rule<T, I>]) -> HashSet<usize> {
+    let mut nullable = HashSet::new();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for rule in rules {
+            let r = rule.0.borrow();
+
+            if !nullable.contains(&rule.id()) && r.instr.iter().all(|i| instr_nullable(i, &nullable)) {
+                nullable.insert(rule.id());
+                changed = true;
+            }
+        }
+    }
+
+    nullable
+}
+
+fn instr_nullable<T, I: Element>(instr: &Instr<Rule<T, I>, I>, nullable: &HashSet<usize>) -> bool {
+    match *instr {
+        Instr::And(_) | Instr::Eof | Instr::Guard(_) | Instr::NoBacktrack(_) | Instr::Not(_) => true,
+        Instr::AnyOf(ref alts) => alts.iter().any(|r| nullable.contains(&r.id())),
+        Instr::Precedence(ref atom, _) => nullable.contains(&atom.id()),
+        Instr::Range(min, _, ref rule) => min == 0 || nullable.contains(&rule.id()),
+        Instr::SepBy(min, _, ref item, _, _) => min == 0 || nullable.contains(&item.id()),
+        _ => false,
+    }
+}
+
+// A rule that can fail without consuming input, like `eof`, `not` or a guard, is nullable but not
+// infallible.
+fn infallible<T, I: Element>(rules: &[Rule<T, I>]) -> HashSet<usize> {
+    let mut infallible = HashSet::new();
+    let mut changed = true;
+
+    while changed {
+        changed = false;
+
+        for rule in rules {
+            let r = rule.0.borrow();
+
+            if !infallible.contains(&rule.id()) && r.instr.iter().all(|i| instr_infallible(i, &infallible)) {
+                infallible.insert(rule.id());
+                changed = true;
+            }
+        }
+    }
+
+    infallible
+}
+
+fn instr_infallible<T, I: Element>(instr: &Instr<Rule<T, I>, I>, infallible: &HashSet<usize>) -> bool {
+    match *instr {
+        Instr::NoBacktrack(_) => true,
+        Instr::And(ref rule) => infallible.contains(&rule.id()),
+        Instr::AnyOf(ref alts) => alts.iter().any(|r| infallible.contains(&r.id())),
+        Instr::Precedence(ref atom, _) => infallible.contains(&atom.id()),
+        Instr::Range(min, _, ref rule) => min == 0 || infallible.contains(&rule.id()),
+        Instr::SepBy(min, _, ref item, ref sep, _) => min == 0 || (infallible.contains(&item.id()) && (min == 1 || infallible.contains(&sep.id()))),
+        _ => false,
+    }
+}
+
+// The rules which can be entered at the position where a rule starts.
+fn left_edges<T, I: Element>(rules: &[Rule<T, I>], nullable: &HashSet<usize>) -> HashMap<usize, Vec<usize>> {
+    let mut edges = HashMap::new();
+
+    for rule in rules {
+        let r = rule.0.borrow();
+        let mut first = Vec::new();
+
+        for instr in &r.instr {
+            match *instr {
+                Instr::And(ref rule) | Instr::Not(ref rule) | Instr::Range(_, _, ref rule) | Instr::SepBy(_, _, ref rule, _, _) => first.push(rule.id()),
+                Instr::AnyOf(ref alts) => first.extend(alts.iter().map(Rule::id)),
+                Instr::Precedence(ref atom, ref ops) => {
+                    first.push(atom.id());
+                    first.extend(ops.iter().filter(|op| op.left_power().is_none()).map(|op| op.op.id()));
+                },
+                _ => (),
+            }
+
+            if !instr_nullable(instr, nullable) {
+                break;
+            }
+        }
+
+        edges.insert(rule.id(), first);
+    }
+
+    edges
+}
+
+fn reachable(edges: &HashMap<usize, Vec<usize>>, from: usize) -> HashSet<usize> {
+    let mut reach = HashSet::new();
+    let mut stack = edges[&from].clone();
+
+    while let Some(id) = stack.pop() {
+        if reach.insert(id) {
+            stack.extend(edges[&id].iter().cloned());
+        }
+    }
+
+    reach
+}
+
+// The literal text a rule starts with, and whether that's all it matches.
+fn literal_prefix<T, I: Element>(rule: &Rule<T, I>) -> (String, bool) {
+    let mut text = String::new();
+    let complete = push_literal(rule, &mut text, &mut Vec::new()) && !text.is_empty();
+    (text, complete)
+}
+
+fn push_literal<T, I: Element>(rule: &Rule<T, I>, text: &mut String, stack: &mut Vec<usize>) -> bool {
+    if stack.contains(&rule.id()) {
+        return false;
+    }
+
+    stack.push(rule.id());
+    let r = rule.0.borrow();
+
+    let complete = r.instr.iter().all(|instr| match *instr {
+        Instr::Literal(literal) => {
+            text.push_str(literal);
+            true
+        },
+        Instr::LiteralString(ref literal) => {
+            text.push_str(literal);
+            true
+        },
+        Instr::Range(1, 1, ref rule) => push_literal(rule, text, stack),
+        _ => false,
+    });
+
+    stack.pop();
+    complete
+}
+
+// A rule is referred to by its name, or written out when it has none.
+fn describe<T, I: Element>(rule: &Rule<T, I>) -> String {
+    match rule.0.borrow().name {
+        Some(ref name) => name.clone(),
+        None => rule.to_string().lines().next().unwrap_or_default().to_string(),
+    }
+}
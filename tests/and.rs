@@ -0,0 +1,69 @@
+use rule::{Grammar, Rule};
+
+#[test]
+fn and_literal() {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let word: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    word.at_least(1, Rule::default().char_in('a', 'z'));
+
+    // A word which is followed by a digit, the digit itself is not part of the word.
+    let r: Rule<String> = Rule::default();
+    r.one(&word).and(&digit).any_char();
+
+    assert_eq!(r.scan("abc1").unwrap(), vec!["abc"]);
+    assert!(r.scan("abc-").is_err());
+    assert!(r.scan("abc").is_err());
+}
+
+#[test]
+fn and_no_branches() {
+    let x: Rule<i32> = Rule::new(|_, _| Ok(7));
+    x.literal("x");
+
+    let r: Rule<i32> = Rule::default();
+    r.peek(&x).one(&x);
+
+    assert_eq!(r.scan("x").unwrap(), vec![7]);
+}
+
+#[test]
+fn and_expected() {
+    let r: Rule<i32> = Rule::default();
+    r.and(Rule::default().literal("monkey")).any_char().any_char();
+
+    let err = r.scan("pizza").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 0: Expected \"monkey\".");
+}
+
+#[test]
+fn and_no_backtrack() {
+    let a = Rule::default();
+    a.literal("a").no_backtrack("Oops!".to_string()).literal("b");
+
+    let peek_a = Rule::default();
+    peek_a.and(&a).literal("ab");
+
+    let r: Rule<i32> = Rule::default();
+    r.any_of(vec![&peek_a, Rule::default().literal("ac")]);
+
+    assert!(r.scan("ab").is_ok());
+    assert!(r.scan("ac").is_ok());
+
+    let r: Rule<i32> = Rule::default();
+    r.one(&a);
+
+    let err = r.scan("ac").unwrap_err();
+    assert_eq!(err.msg, "Oops!");
+}
+
+#[test]
+fn and_grammar() {
+    let mut grammar: Grammar<String> = Grammar::new();
+    grammar.add("word", "[a-z]+", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("root", "((word &'(' '()' | word) ' '?)+", None).unwrap();
+
+    assert_eq!(grammar.scan("root", "foo() bar").unwrap(), vec!["foo", "bar"]);
+    assert_eq!(format!("{}", grammar.rule("root").unwrap()), "root = ((word &\"(\" \"()\" | word) \" \"?)+\nword = [a-z]+");
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants, clippy::bool_assert_comparison, clippy::needless_borrow)]
+
 use rule::Rule;
 
 #[test]
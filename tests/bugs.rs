@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants)]
+
 /*
 
     The following bug was in Rule v0.5.12.
@@ -0,0 +1,87 @@
+use rule::{Grammar, Rule};
+
+#[test]
+fn literal_ci_keeps_input() {
+    let select: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    select.literal_ci("select");
+
+    assert_eq!(select.scan("SeLeCt").unwrap(), vec!["SeLeCt"]);
+    assert_eq!(select.scan("select").unwrap(), vec!["select"]);
+    assert!(select.scan("selec").is_err());
+    assert!(select.scan("selects").is_err());
+
+    let street: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    street.literal_string_ci(String::from("straße"));
+    assert_eq!(street.scan("STRAßE").unwrap(), vec!["STRAßE"]);
+    assert!(street.scan("STRASSE").is_err());
+}
+
+#[test]
+fn literal_ci_unicode() {
+    let sigma: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    sigma.literal_ci("σοφία");
+    assert_eq!(sigma.scan("ΣΟΦΊΑ").unwrap(), vec!["ΣΟΦΊΑ"]);
+
+    let final_sigma: Rule<bool> = Rule::default();
+    final_sigma.literal_ci("ς");
+    assert!(final_sigma.scan("Σ").is_ok());
+    assert!(final_sigma.scan("σ").is_ok());
+
+    let kelvin: Rule<bool> = Rule::default();
+    kelvin.literal_ci("k");
+    assert!(kelvin.scan("\u{212A}").is_ok());
+
+    // The dotless 'ı' and dotted 'İ' only fold to 'i' in Turkic languages.
+    let i: Rule<bool> = Rule::default();
+    i.literal_ci("i");
+    assert!(i.scan("I").is_ok());
+    assert!(i.scan("ı").is_err());
+    assert!(i.scan("İ").is_err());
+
+    let dotless_i: Rule<bool> = Rule::default();
+    dotless_i.literal_ci("ı");
+    assert!(dotless_i.scan("ı").is_ok());
+    assert!(dotless_i.scan("I").is_err());
+
+    let dotted_i: Rule<bool> = Rule::default();
+    dotted_i.literal_ci("İ");
+    assert!(dotted_i.scan("İ").is_ok());
+    assert!(dotted_i.scan("i").is_err());
+
+    let long_s: Rule<bool> = Rule::default();
+    long_s.literal_ci("ſ");
+    assert!(long_s.scan("S").is_ok());
+
+    let cherokee: Rule<bool> = Rule::default();
+    cherokee.literal_ci("\u{AB70}\u{13F8}");
+    assert!(cherokee.scan("\u{13A0}\u{13F0}").is_ok());
+}
+
+#[test]
+fn alter_ci() {
+    let bool_lit: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    bool_lit.alter_ci(vec![("true", "1"), ("false", "0")]);
+    assert_eq!(bool_lit.scan("TRUE").unwrap(), vec!["1"]);
+    assert_eq!(bool_lit.scan("False").unwrap(), vec!["0"]);
+    assert!(bool_lit.scan("yes").is_err());
+
+    let yes: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    yes.alter_string_ci(vec![(String::from("ja"), String::from("yes"))]);
+    assert_eq!(yes.scan("JA").unwrap(), vec!["yes"]);
+    assert!(yes.scan("j").is_err());
+}
+
+#[test]
+fn grammar_literal_ci() {
+    let mut grammar: Grammar<String> = Grammar::new();
+    grammar.add("kw", "'select'i ' ' id", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("id", "[a-z]+", None).unwrap();
+    assert_eq!(grammar.scan("kw", "SELECT abc").unwrap(), vec!["SELECT abc"]);
+    assert!(grammar.scan("kw", "SELECT ABC").is_err());
+    assert_eq!(format!("{}", grammar.rule("kw").unwrap()), "kw = \"select\"i \" \" id\nid = [a-z]+");
+
+    let mut grammar: Grammar<bool> = Grammar::new();
+    grammar.add("a", "'x'id", None).unwrap();
+    grammar.add("id", "'y'", None).unwrap();
+    assert!(grammar.scan("a", "xy").is_ok());
+}
@@ -0,0 +1,60 @@
+use std::thread;
+use rule::{CharClass, GeneralCategory, Rule};
+
+fn ident() -> Rule<String> {
+    let start = Rule::default();
+    start.char_class(CharClass::XidStart);
+
+    let cont = Rule::default();
+    cont.char_class(CharClass::XidContinue);
+
+    let ident = Rule::new_sync(|_, l| Ok(l.to_string()));
+    ident.one(&start).none_or_many(&cont);
+    ident
+}
+
+#[test]
+fn char_class_identifiers() {
+    let ident = ident();
+    assert_eq!(ident.scan("größe_2").unwrap(), vec!["größe_2"]);
+    assert_eq!(ident.scan("Δx").unwrap(), vec!["Δx"]);
+    assert_eq!(ident.scan("変数").unwrap(), vec!["変数"]);
+    assert!(ident.scan("2x").is_err());
+    assert!(ident.scan("a-b").is_err());
+
+    let err = ident.scan("_").unwrap_err();
+    assert_eq!(err.msg, "Expected \\p{XID_Start}.");
+}
+
+#[test]
+fn char_class_std_and_categories() {
+    let class = |class: CharClass, code: &str| {
+        let r: Rule<bool> = Rule::default();
+        r.at_least(1, Rule::default().char_class(class));
+        r.scan(code).is_ok()
+    };
+
+    assert!(class(CharClass::Alphabetic, "aßΩ"));
+    assert!(!class(CharClass::Alphabetic, "a1"));
+    assert!(class(CharClass::Alphanumeric, "a1٣"));
+    assert!(class(CharClass::Numeric, "12٣½"));
+    assert!(class(CharClass::Whitespace, " \t\u{3000}"));
+    assert!(class(CharClass::Lowercase, "aß"));
+    assert!(class(CharClass::Uppercase, "AΩ"));
+    assert!(class(CharClass::Category(GeneralCategory::DecimalNumber), "0٣"));
+    assert!(!class(CharClass::Category(GeneralCategory::DecimalNumber), "½"));
+    assert!(class(CharClass::Category(GeneralCategory::CurrencySymbol), "$€"));
+}
+
+#[test]
+fn char_if() {
+    let hex: Rule<u32> = Rule::new_sync(|_, l| u32::from_str_radix(l, 16).map_err(|e| e.to_string()));
+    hex.at_least(1, Rule::default().char_if(|c| c.is_ascii_hexdigit()));
+    assert_eq!(hex.scan("ff").unwrap(), vec![255]);
+    assert!(hex.scan("fg").is_err());
+    assert_eq!(format!("{}", hex), "char_if(..)+");
+
+    let frozen = hex.freeze().unwrap();
+    assert_eq!(thread::spawn(move || frozen.scan("1A").unwrap()).join().unwrap(), vec![26]);
+    assert_eq!(format!("{}", ident()), "\\p{XID_Start} \\p{XID_Continue}*");
+}
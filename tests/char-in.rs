@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants, clippy::legacy_numeric_constants, clippy::redundant_pattern_matching)]
+
 use rule::Rule;
 
 #[test]
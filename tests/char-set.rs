@@ -0,0 +1,86 @@
+use rule::{Grammar, Rule};
+
+#[test]
+fn char_set_ranges() {
+    let ident: Rule<bool> = Rule::default();
+    ident.at_least(1, Rule::default().char_set(vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]));
+
+    assert!(ident.scan("foo_Bar9").is_ok());
+    assert!(ident.scan("foo-bar").is_err());
+    assert!(ident.scan("").is_err());
+
+    let err = Rule::<bool>::default().char_set(vec![('x', 'z'), ('a', 'c'), ('b', 'f'), ('g', 'g'), ('_', '_')]).scan("-").unwrap_err();
+    assert_eq!(err.msg, "Expected [_a-gx-z].");
+}
+
+#[test]
+fn char_set_except() {
+    let text: Rule<bool> = Rule::default();
+    text.none_or_many(Rule::default().char_set_except(vec![('"', '"'), ('\\', '\\')]));
+
+    assert!(text.scan("abc 東東💝").is_ok());
+    assert!(text.scan("a\"b").is_err());
+    assert!(text.scan("a\\b").is_err());
+
+    let err = Rule::<bool>::default().char_set_except(vec![('0', '9')]).scan("5").unwrap_err();
+    assert_eq!(err.msg, "Expected [^0-9].");
+}
+
+#[test]
+fn char_set_expr() {
+    let set: Rule<bool> = Rule::default();
+    set.at_least(1, Rule::default().char_set_expr("[^a-z\\]]"));
+
+    assert!(set.scan("ABC-09[").is_ok());
+    assert!(set.scan("AbC").is_err());
+    assert!(set.scan("A]").is_err());
+
+    let set: Rule<bool> = Rule::default();
+    set.at_least(1, Rule::default().char_set_expr("[]a-c\\-]"));
+
+    assert!(set.scan("]ab-c").is_ok());
+    assert!(set.scan("d").is_err());
+    assert_eq!(format!("{}", set), "[\\-\\]a-c]+");
+}
+
+#[test]
+fn char_set_expr_dash() {
+    // A "-" at the start or right before the "]" is the character itself.
+    let ident: Rule<bool> = Rule::default();
+    ident.at_least(1, Rule::default().char_set_expr("[A-Za-z0-9_-]")).eof();
+
+    assert!(ident.scan("snake_case-Kebab9").is_ok());
+    assert!(ident.scan("a.b").is_err());
+
+    let set: Rule<bool> = Rule::default();
+    set.at_least(1, Rule::default().char_set_expr("[-+]"));
+
+    assert!(set.scan("+-").is_ok());
+
+    let mut grammar: Grammar<String> = Grammar::new();
+    grammar.add("word", "[a-z_-]+", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+
+    assert_eq!(grammar.scan("word", "foo-bar_baz").unwrap(), vec!["foo-bar_baz"]);
+}
+
+#[test]
+#[should_panic(expected = "Invalid character set")]
+fn char_set_expr_invalid() {
+    Rule::<bool>::default().char_set_expr("[a-");
+}
+
+#[test]
+#[should_panic(expected = "Invalid character range.")]
+fn char_set_invalid_range() {
+    Rule::<bool>::default().char_set(vec![('z', 'a')]);
+}
+
+#[test]
+fn char_set_grammar() {
+    let mut grammar: Grammar<String> = Grammar::new();
+    grammar.add("word", "[a-zA-Z_]+", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("other", "[^a-zA-Z_ ]+", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("root", "(word | other) (' ' (word | other))*", None).unwrap();
+
+    assert_eq!(grammar.scan("root", "foo 123 b_r").unwrap(), vec!["foo", "123", "b_r"]);
+}
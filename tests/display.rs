@@ -0,0 +1,91 @@
+use rule::{Grammar, Rule};
+
+#[test]
+fn display_named_rules() {
+    let expr: Rule<bool> = Rule::default();
+    expr.set_name("expr");
+
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let ident = Rule::default();
+    ident.set_name("identifier").at_least(1, &letter);
+
+    let block = Rule::default();
+    block.set_name("block").literal("{").none_or_many(&expr).literal("}");
+
+    let else_block = Rule::default();
+    else_block.literal("else").one(&block);
+
+    let if_else = Rule::default();
+    if_else.set_name("if").literal("if").one(&expr).no_backtrack(String::from("Block expected.")).one(&block).maybe(&else_block);
+
+    expr.any_of(vec![&if_else, &ident]);
+
+    assert_eq!(format!("{}", if_else), "if = \"if\" expr ~ block (\"else\" block)?\n\
+        expr = if | identifier\n\
+        block = \"{\" expr* \"}\"\n\
+        identifier = [a-z]+");
+
+    assert_eq!(format!("{:?}", ident), "identifier = [a-z]+");
+}
+
+#[test]
+fn display_unnamed_rules() {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let sign = Rule::default();
+    sign.alter(vec![("+", ""), ("-", "-")]);
+
+    let num: Rule<bool> = Rule::default();
+    num.maybe(&sign).between(1, 3, &digit).not(Rule::default().any_char_except(vec![']', '\n'])).eof();
+    assert_eq!(format!("{}", num), "(\"+\" | \"-\")? [0-9]{1,3} ![^\\]\\n] $");
+
+    let list: Rule<bool> = Rule::default();
+    let item = Rule::default();
+    item.any_of(vec![Rule::default().literal("x"), Rule::default().literal("(").one(&list).literal(")")]);
+    list.at_least(2, &item);
+    assert_eq!(format!("{}", list), "(\"x\" | \"(\" ... \")\"){2,}");
+
+    assert_eq!(format!("{}", Rule::<bool>::default()), "()");
+}
+
+#[test]
+fn display_grammar() {
+    let mut grammar: Grammar<bool> = Grammar::new();
+    grammar.add("num", "[0-9]+", None).unwrap();
+    grammar.add("sum", "num ('+' num)*", None).unwrap();
+    assert_eq!(format!("{}", grammar.rule("sum").unwrap()), "sum = num (\"+\" num)*\nnum = [0-9]+");
+}
+
+#[test]
+fn named_rules_are_expected() {
+    let keyword = Rule::default();
+    keyword.literal("then");
+
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let ident = Rule::named("identifier", |_, _| Ok(true));
+    ident.not(&keyword).at_least(1, &letter);
+
+    let close = Rule::default();
+    close.literal(")");
+
+    let root = Rule::default();
+    root.literal("(").one(&ident).literal(" ").any_of(vec![Rule::default().literal("then"), &ident, &close]);
+
+    let err = root.scan("(a 1").unwrap_err();
+    assert_eq!(err.msg, "Expected \"then\", identifier or \")\".");
+
+    let err = root.scan("(then").unwrap_err();
+    assert_eq!(err.expected, vec!["identifier"]);
+
+    // Failing inside a named rule reports the leaves there.
+    let call = Rule::named("call", |_, _| Ok(true));
+    call.one(&ident).literal("(").literal(")");
+
+    let err = call.scan("f(").unwrap_err();
+    assert_eq!(err.expected, vec!["\")\""]);
+}
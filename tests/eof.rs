@@ -1,4 +1,6 @@
-use rule::Rule;
+#![allow(clippy::assertions_on_constants, clippy::needless_borrow)]
+
+use rule::{Grammar, Rule};
 
 #[test]
 fn eof() {
@@ -13,4 +15,17 @@ fn eof() {
     else {
         assert!(false);
     }
-}
\ No newline at end of file
+}
+#[test]
+fn eof_then_leaf() {
+    let r: Rule<char> = Rule::default();
+    r.eof().literal("x");
+
+    let err = r.scan("").unwrap_err();
+    assert_eq!((err.line, err.col, err.start_char), (1, 0, 0));
+    assert_eq!(err.msg, "Expected \"x\".");
+
+    let mut grammar: Grammar<char> = Grammar::new();
+    grammar.add("root", "$ 'x'", None).unwrap();
+    assert!(grammar.scan("root", "").is_err());
+}
@@ -0,0 +1,65 @@
+use rule::{Grammar, Rule, RuleErrorKind};
+
+#[test]
+fn error_kind_syntax() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("a").literal("b");
+
+    assert_eq!(r.scan("ax").unwrap_err().kind, RuleErrorKind::Syntax);
+}
+
+#[test]
+fn error_kind_committed() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("let").no_backtrack(String::from("Expected a name after let.")).char_in('a', 'z');
+
+    let err = r.scan("let1").unwrap_err();
+    assert_eq!(err.kind, RuleErrorKind::Committed { label: String::from("Expected a name after let.") });
+    assert_eq!(err.msg, "Expected a name after let.");
+}
+
+#[test]
+fn error_kind_semantic() {
+    let num: Rule<i32> = Rule::new(|_, l| l.parse().map_err(|_| format!("{} is too large.", l)));
+    num.at_least(1, Rule::default().char_in('0', '9'));
+
+    let r: Rule<i32> = Rule::default();
+    r.literal("=").one(&num);
+
+    let err = r.scan("=99999999999").unwrap_err();
+    assert_eq!(err.kind, RuleErrorKind::Semantic);
+    assert_eq!(err.col, 1);
+}
+
+#[test]
+fn error_kind_trailing_input() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("a");
+
+    let err = r.scan("ab").unwrap_err();
+    assert_eq!(err.kind, RuleErrorKind::TrailingInput);
+    assert_eq!(err.expected, vec!["end of input"]);
+}
+
+#[test]
+fn error_kind_undefined_rule() {
+    let mut grammar: Grammar<i32> = Grammar::new();
+    grammar.add("root", "'a' <missing>", None).unwrap();
+
+    assert_eq!(grammar.scan("root", "a").unwrap_err().kind, RuleErrorKind::UndefinedRule);
+    assert_eq!(grammar.scan("other", "a").unwrap_err().kind, RuleErrorKind::UndefinedRule);
+}
+
+#[test]
+fn error_kind_recovered() {
+    let stmt: Rule<i32> = Rule::new(|_, _| Ok(1));
+    stmt.literal("x").no_backtrack(String::from("Expected ';'.")).literal(";");
+    stmt.recover_to(Rule::default().literal(";"));
+
+    let r: Rule<i32> = Rule::default();
+    r.at_least(1, &stmt);
+
+    let (_, errors) = r.scan_recover("x;x!;x;");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, RuleErrorKind::Committed { label: String::from("Expected ';'.") });
+}
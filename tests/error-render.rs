@@ -0,0 +1,77 @@
+use rule::{Grammar, Rule};
+
+#[test]
+fn error_offsets() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("ab\n").literal("€").literal("d");
+
+    let err = r.scan("ab\n€x").unwrap_err();
+    assert_eq!((err.line, err.col), (2, 1));
+    assert_eq!((err.start_byte, err.start_char), (6, 4));
+    assert_eq!(err.end, Some((7, 5)));
+}
+
+#[test]
+fn error_offsets_end_of_input() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("a").literal("b");
+
+    let err = r.scan("a").unwrap_err();
+    assert_eq!((err.start_byte, err.start_char), (1, 1));
+    assert_eq!(err.end, None);
+}
+
+#[test]
+fn error_render() {
+    let code = "let a = 1;\nlet b = ?;\nlet c = 3;";
+
+    let mut grammar: Grammar<i32> = Grammar::new();
+    grammar.add("stmt", "'let ' [a-z] ' = ' [0-9] ';'", None).unwrap();
+    grammar.add("root", "<stmt> ('\n' <stmt>)* $", None).unwrap();
+
+    let err = grammar.scan("root", code).unwrap_err();
+
+    assert_eq!(err.render(code), concat!(
+        "error: Expected '0'-'9'.\n",
+        " --> line 2, column 8\n",
+        "  |\n",
+        "2 | let b = ?;\n",
+        "  |         ^\n",
+    ));
+}
+
+#[test]
+fn error_render_end_of_input() {
+    let code = "\tfoo(";
+
+    let r: Rule<i32> = Rule::default();
+    r.literal("\tfoo(").literal(")");
+
+    let err = r.scan(code).unwrap_err();
+
+    assert_eq!(err.render(code), concat!(
+        "error: Expected \")\".\n",
+        " --> line 1, column 5\n",
+        "  |\n",
+        "1 | \tfoo(\n",
+        "  | \t    ^\n",
+    ));
+}
+
+#[test]
+fn error_render_wide_line_number() {
+    let code = "\n\n\n\n\n\n\n\n\nx\r\ny";
+
+    let r: Rule<i32> = Rule::default();
+    r.exact(9, Rule::default().literal("\n")).literal("y");
+
+    let err = r.scan(code).unwrap_err();
+
+    assert_eq!(err.render(code), concat!(
+        "error: Expected \"y\".\n",
+        "  --> line 10, column 0\n",
+        "   |\n",
+        "10 | x\n",
+        "   | ^\n",
+    ));
+}
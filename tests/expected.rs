@@ -0,0 +1,62 @@
+use rule::Rule;
+
+fn if_then() -> Rule<bool> {
+    let space = Rule::default();
+    space.literal(" ");
+
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let ident = Rule::default();
+    ident.at_least(1, &letter);
+
+    let group = Rule::default();
+    group.literal("(").one(&ident).literal(")");
+
+    let cond = Rule::default();
+    cond.any_of(vec![&ident, &group]);
+
+    let root = Rule::default();
+    root.literal("if").at_least(1, &space).one(&cond).at_least(1, &space).literal("then").at_least(1, &space).one(&ident);
+    root
+}
+
+#[test]
+fn expected_at_farthest_failure() {
+    let err = if_then().scan("if x else y").unwrap_err();
+    assert_eq!((err.line, err.col), (1, 5));
+    assert_eq!(err.expected, vec!["\" \"", "\"then\""]);
+    assert_eq!(err.msg, "Expected \" \" or \"then\".");
+
+    let err = if_then().scan("if (x then y").unwrap_err();
+    assert_eq!((err.line, err.col), (1, 5));
+    assert_eq!(err.expected, vec!["'a'-'z'", "\")\""]);
+
+    let err = if_then().scan("if").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 2: Expected \" \".");
+}
+
+#[test]
+fn expected_end_of_input() {
+    let err = if_then().scan("if x then y!").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 11: Expected 'a'-'z' or end of input.");
+}
+
+#[test]
+fn expected_ignores_not() {
+    let keyword = Rule::default();
+    keyword.literal("then");
+
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let ident: Rule<bool> = Rule::default();
+    ident.not(&keyword).at_least(1, &letter);
+
+    let err = ident.scan("1").unwrap_err();
+    assert_eq!(err.expected, vec!["'a'-'z'"]);
+
+    let err = ident.scan("then").unwrap_err();
+    assert!(err.expected.is_empty());
+    assert_eq!(err.msg, "Syntax error.");
+}
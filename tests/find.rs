@@ -0,0 +1,107 @@
+#![allow(clippy::assertions_on_constants, clippy::needless_borrow)]
+
+use rule::Rule;
+
+fn number() -> Rule<u32> {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let number = Rule::new_sync(|_, l| l.parse().map_err(|_| String::from("NaN")));
+    number.at_least(1, &digit);
+    number
+}
+
+#[test]
+fn find_first_match() {
+    let number = number();
+
+    if let Some(m) = number.find("Ω room 42, floor 7") {
+        assert_eq!(m.branches, vec![42]);
+        assert_eq!(m.text, "42");
+        assert_eq!((m.start, m.end), (8, 10));
+        assert_eq!((m.char_start, m.char_end), (7, 9));
+    }
+    else {
+        assert!(false);
+    }
+
+    assert!(number.find("no numbers here").is_none());
+}
+
+#[test]
+fn find_iter_all_matches() {
+    let number = number();
+    let found: Vec<u32> = number.find_iter("1, 22 and ñ333").map(|m| m.branches[0]).collect();
+    assert_eq!(found, vec![1, 22, 333]);
+
+    let spans: Vec<(usize, usize)> = number.find_iter("ñ1ñ22").map(|m| (m.char_start, m.char_end)).collect();
+    assert_eq!(spans, vec![(1, 2), (3, 5)]);
+}
+
+#[test]
+fn find_iter_empty_matches() {
+    let a: Rule<u32> = Rule::default();
+    a.literal("a");
+
+    let maybe_a: Rule<u32> = Rule::default();
+    maybe_a.maybe(&a);
+
+    let texts: Vec<&str> = maybe_a.find_iter("baab").map(|m| m.text).collect();
+    assert_eq!(texts, vec!["", "a", "a", "", ""]);
+}
+
+#[test]
+fn replace_all_matches() {
+    let number = number();
+    let doubled = number.replace_all("a1b20c", |m| format!("{}", m.branches[0] * 2));
+    assert_eq!(doubled, "a2b40c");
+    assert_eq!(number.replace_all("abc", |_| String::from("x")), "abc");
+}
+
+#[test]
+fn split_on_matches() {
+    let space: Rule<u32> = Rule::default();
+    space.literal(",").none_or_many(&Rule::default().literal(" "));
+
+    let parts: Vec<&str> = space.split("a, b,c,, d").collect();
+    assert_eq!(parts, vec!["a", "b", "c", "", "d"]);
+
+    let parts: Vec<&str> = space.split("").collect();
+    assert_eq!(parts, vec![""]);
+}
+
+#[test]
+fn find_frozen() {
+    let number = number().freeze();
+
+    if let Ok(number) = number {
+        let found: Vec<&str> = number.find_iter("x12y3").map(|m| m.text).collect();
+        assert_eq!(found, vec!["12", "3"]);
+        assert_eq!(number.find("x12").map(|m| m.start), Some(1));
+    }
+    else {
+        assert!(false);
+    }
+}
+
+#[test]
+fn find_branch_error() {
+    let ab: Rule<char> = Rule::new(|_, l| match l {
+        "b" => Err(String::from("No b.")),
+        _ => Ok(l.chars().next().unwrap()),
+    });
+    ab.char_in('a', 'b');
+
+    // `find` skips the position with the error, `try_find` stops at it.
+    assert_eq!(ab.find("zzb a").map(|m| m.start), Some(4));
+
+    let err = ab.try_find("zzb a").err().unwrap();
+    assert_eq!(err.msg, "No b.");
+    assert_eq!(err.start_char, 2);
+
+    assert_eq!(ab.try_find("zz a").unwrap().map(|m| m.branches), Some(vec!['a']));
+    assert!(ab.try_find("zz").unwrap().is_none());
+
+    let found: Vec<usize> = ab.find_iter("ab a").map(|m| m.start).collect();
+    assert_eq!(found, vec![0, 3]);
+}
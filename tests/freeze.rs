@@ -0,0 +1,95 @@
+#![allow(clippy::assertions_on_constants)]
+
+use std::sync::Arc;
+use std::thread;
+use rule::{FrozenRule, Grammar, GrammarError, Rule};
+
+fn calc() -> Rule<f64> {
+    let expr: Rule<f64> = Rule::default();
+
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let num = Rule::new_sync(|_, l| l.parse().map_err(|x| format!("{}", x)));
+    num.at_least(1, &digit);
+
+    let group = Rule::default();
+    group.literal("(").one(&expr).literal(")");
+
+    let atom = Rule::default();
+    atom.any_of(vec![&num, &group]);
+
+    let mul = Rule::default();
+    let times = Rule::new_sync(|b, _| Ok(b[0] * b[1]));
+    times.one(&mul).literal("*").one(&atom);
+    mul.any_of(vec![&times, &atom]).memoize();
+
+    let add = Rule::default();
+    let plus = Rule::new_sync(|b, _| Ok(b[0] + b[1]));
+    plus.one(&add).literal("+").one(&mul);
+    add.any_of(vec![&plus, &mul]);
+
+    expr.one(&add);
+    expr
+}
+
+#[test]
+fn freeze_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FrozenRule<f64>>();
+    assert_send_sync::<FrozenRule<std::rc::Rc<u32>>>();
+}
+
+#[test]
+fn freeze_scan_from_threads() {
+    let calc = Arc::new(calc().freeze().unwrap());
+
+    let handles: Vec<_> = (0..4u32).map(|i| {
+        let calc = calc.clone();
+        thread::spawn(move || calc.scan(&format!("2*(3+{})*5", i)).unwrap()[0])
+    }).collect();
+
+    let results: Vec<f64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(results, vec![30f64, 40f64, 50f64, 60f64]);
+
+    if let Err(err) = calc.scan("2*(3+4") {
+        assert_eq!(format!("{}", err), "Error found at line 1, column 6: Expected '0'-'9', \"*\", \"+\" or \")\".");
+    }
+    else {
+        assert!(false);
+    }
+}
+
+#[test]
+fn freeze_leaves_rule_usable() {
+    let calc = calc();
+    let frozen = calc.freeze().unwrap();
+    assert_eq!(calc.scan("1+2*3").unwrap(), frozen.scan("1+2*3").unwrap());
+}
+
+#[test]
+fn freeze_rejects_non_sync_branch_fn() {
+    let word: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    word.literal("word");
+
+    let root: Rule<String> = Rule::default();
+    root.one(&word);
+
+    match root.freeze() {
+        Err(GrammarError::Invalid(msg)) => assert_eq!(msg, "Branch function is not thread safe, use `Rule::new_sync` instead."),
+        _ => panic!("Expected an invalid rule."),
+    }
+}
+
+#[test]
+fn freeze_grammar() {
+    let mut grammar: Grammar<u32> = Grammar::new();
+    grammar.add_sync("num", "[0-9]+", Some(Arc::new(|_, l| l.parse().map_err(|_| String::from("NaN"))))).unwrap();
+    grammar.add_sync("sum", "<num> ('+' <num>)*", Some(Arc::new(|b, _| Ok(b.iter().sum())))).unwrap();
+
+    let sum = grammar.rule("sum").unwrap().freeze().unwrap();
+    assert_eq!(thread::spawn(move || sum.scan("1+2+3").unwrap()).join().unwrap(), vec![6]);
+
+    grammar.add("other", "<num>", Some(Box::new(|b, _| Ok(b[0])))).unwrap();
+    assert!(grammar.rule("other").unwrap().freeze().is_err());
+}
@@ -0,0 +1,108 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rule::Grammar;
+
+#[test]
+fn grammar_calc() {
+    let mut grammar: Grammar<f64> = Grammar::new();
+    grammar.add("num", "[0-9]+ ('.' [0-9]+)?", Some(Box::new(|_, l| l.parse().map_err(|x| format!("{}", x))))).unwrap();
+    grammar.add("group", "'(' <expr> ')'", None).unwrap();
+    grammar.add("atom", "<num> | <group>", None).unwrap();
+    grammar.add("mul", "<atom> ('*' <atom>)*", Some(Box::new(|b, _| Ok(b.iter().product())))).unwrap();
+    grammar.add("expr", "<mul> ('+' <mul>)*", Some(Box::new(|b, _| Ok(b.iter().sum())))).unwrap();
+
+    if let Ok(branches) = grammar.scan("expr", "2*(3+4)*5+0.5") {
+        assert_eq!(branches[0], 70.5f64);
+    }
+    else {
+        assert!(false);
+    }
+
+    assert!(grammar.scan("expr", "2*(3+4").is_err());
+}
+
+#[test]
+fn grammar_quantifiers() {
+    let mut grammar: Grammar<u32> = Grammar::new();
+    grammar.add("x", "'x'", Some(Box::new(|_, _| Ok(1)))).unwrap();
+    grammar.add("two_to_three", "x{2,3} $", None).unwrap();
+    grammar.add("exact", "x{2}", None).unwrap();
+    grammar.add("at_most", "x{,2}", None).unwrap();
+    grammar.add("at_least", "x{2,}", None).unwrap();
+    grammar.add("maybe", "x? 'y'", None).unwrap();
+
+    assert!(grammar.scan("two_to_three", "x").is_err());
+    assert_eq!(grammar.scan("two_to_three", "xx").unwrap().len(), 2);
+    assert_eq!(grammar.scan("two_to_three", "xxx").unwrap().len(), 3);
+    assert!(grammar.scan("two_to_three", "xxxx").is_err());
+    assert!(grammar.scan("exact", "xxx").is_err());
+    assert_eq!(grammar.scan("at_most", "").unwrap().len(), 0);
+    assert!(grammar.scan("at_most", "xxx").is_err());
+    assert_eq!(grammar.scan("at_least", "xxxxx").unwrap().len(), 5);
+    assert_eq!(grammar.scan("maybe", "y").unwrap().len(), 0);
+    assert_eq!(grammar.scan("maybe", "xy").unwrap().len(), 1);
+}
+
+#[test]
+fn grammar_class_not_and_escapes() {
+    let mut grammar: Grammar<String> = Grammar::new();
+    grammar.add("ident", "!'if' [a-zA-Z_] [a-zA-Z0-9_]*", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("string", "'\"' ([^\"\\\\] | '\\\\' .)* '\"'", Some(Box::new(|_, l| Ok(l.to_string())))).unwrap();
+    grammar.add("root", "(ident | string) (' '+ (ident | string))*", None).unwrap();
+
+    let branches = grammar.scan("root", "foo \"b\\\"ar\" _baz9").unwrap();
+    assert_eq!(branches, vec!["foo", "\"b\\\"ar\"", "_baz9"]);
+    assert!(grammar.scan("root", "if").is_err());
+    assert!(grammar.scan("root", "9a").is_err());
+}
+
+#[test]
+fn grammar_no_backtrack() {
+    let mut grammar: Grammar<bool> = Grammar::new();
+    grammar.add("root", "'東東' ~ '💝💝💝'", None).unwrap();
+
+    let err = grammar.scan("root", "東東💝").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 2: Syntax error.");
+}
+
+#[test]
+fn grammar_errors() {
+    let mut grammar: Grammar<bool> = Grammar::new();
+
+    let err = grammar.add("a", "'x' ('y' | 'z'", None).unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 14: Expected \")\".");
+
+    let err = grammar.add("a", "'x'\n  'y' |", None).unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 2, column 7: Expression expected.");
+
+    let err = grammar.add("a", "'x'{3,1}", None).unwrap_err();
+    assert_eq!(err.msg, "Minimum is greater than maximum.");
+
+    assert!(grammar.add("a", "'x'", None).is_ok());
+    assert_eq!(grammar.add("a", "'y'", None).unwrap_err().msg, "Rule \"a\" is already defined.");
+
+    grammar.add("b", "<a>\n<c>", None).unwrap();
+    let err = grammar.scan("b", "x").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 2, column 0: Rule \"c\" is not defined, it's referred to by rule \"b\".");
+
+    let err = grammar.scan("d", "x").unwrap_err();
+    assert_eq!(err.msg, "Rule \"d\" is not defined.");
+}
+
+#[test]
+fn grammar_undefined_unreachable() {
+    let mut grammar: Grammar<i32> = Grammar::new();
+    grammar.add("a", "'x'", None).unwrap();
+    grammar.add("b", "<a> <missing>", None).unwrap();
+    grammar.add("c", "<b> | <a>", None).unwrap();
+
+    // Only the rules a rule scans have to be defined.
+    assert!(grammar.scan("a", "x").is_ok());
+    assert_eq!(grammar.scan("c", "x").unwrap_err().msg, "Rule \"missing\" is not defined, it's referred to by rule \"b\".");
+
+    grammar.set_trivia("<space>*").unwrap();
+    assert_eq!(grammar.scan("a", "x").unwrap_err().msg, "Rule \"space\" is not defined, it's referred to by the trivia.");
+
+    grammar.add("space", "' '", None).unwrap();
+    assert!(grammar.scan("a", " x ").is_ok());
+}
@@ -0,0 +1,147 @@
+use rule::{Rule, RuleErrorKind};
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq)]
+enum Stmt {
+    Decl(String, String),
+    Expr(String),
+}
+
+fn stmt(typedefs: &[&str]) -> Rule<Stmt> {
+    let typedefs: Arc<HashSet<String>> = Arc::new(typedefs.iter().map(|t| t.to_string()).collect());
+
+    let ident: Rule<Stmt> = Rule::new(|_, l| Ok(Stmt::Expr(l.to_string())));
+    ident.at_least(1, Rule::default().char_in('a', 'z'));
+
+    // A type name is an identifier which is known as a typedef.
+    let type_name: Rule<Stmt> = Rule::default();
+    type_name.one(&ident).guard(move |l, _| typedefs.contains(l));
+
+    let decl: Rule<Stmt> = Rule::new(|b, l| {
+        match (&b[0], &b[1]) {
+            (Stmt::Expr(t), Stmt::Expr(n)) => Ok(Stmt::Decl(t.clone(), n.clone())),
+            _ => Err(format!("Invalid declaration {}.", l)),
+        }
+    });
+    decl.one(&type_name).literal(" ").one(&ident);
+
+    let r: Rule<Stmt> = Rule::default();
+    r.any_of(vec![&decl, &ident]).literal(";");
+    r
+}
+
+#[test]
+fn guard_typedef() {
+    let r = stmt(&["size"]);
+
+    assert_eq!(r.scan("size n;").unwrap(), vec![Stmt::Decl(String::from("size"), String::from("n"))]);
+    assert_eq!(r.scan("count;").unwrap(), vec![Stmt::Expr(String::from("count"))]);
+    assert_eq!(r.scan("size;").unwrap(), vec![Stmt::Expr(String::from("size"))]);
+
+    // Not a typedef, so the declaration doesn't match and neither does the expression.
+    let err = r.scan("count n;").unwrap_err();
+    assert_eq!(err.kind, RuleErrorKind::Syntax);
+}
+
+#[test]
+fn guard_branches() {
+    let num: Rule<u32> = Rule::new(|_, l| Ok(l.parse().unwrap()));
+    num.at_least(1, Rule::default().char_in('0', '9'));
+
+    let byte: Rule<u32> = Rule::default();
+    byte.one(&num).guard(|_, b| b[0] < 256);
+
+    let big: Rule<u32> = Rule::new(|b, _| Ok(b[0] * 1000));
+    big.one(&num);
+
+    let r: Rule<u32> = Rule::default();
+    r.any_of(vec![&byte, &big]);
+
+    assert_eq!(r.scan("255").unwrap(), vec![255]);
+    assert_eq!(r.scan("256").unwrap(), vec![256000]);
+}
+
+#[test]
+fn guard_in_repetition() {
+    let word: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    word.at_least(1, Rule::default().char_in('a', 'z')).guard(|l, _| l != "end").maybe(Rule::default().literal(" "));
+
+    let r: Rule<String> = Rule::default();
+    r.none_or_many(&word).literal("end");
+
+    assert_eq!(r.scan("a b end").unwrap(), vec!["a ", "b "]);
+}
+
+#[test]
+fn guard_typedef_state() {
+    let ident: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    ident.at_least(1, Rule::default().char_in('a', 'z'));
+
+    // The typedefs are declared earlier in the same input.
+    let typedef: Rule<String> = Rule::with_state(|types: &mut HashSet<String>, b: Vec<String>, _| {
+        types.insert(b[0].clone());
+        Ok(format!("typedef {}", b[0]))
+    });
+    typedef.literal("typedef ").one(&ident).literal(";");
+
+    let type_name: Rule<String> = Rule::default();
+    type_name.one(&ident).guard_with_state(|types: &HashSet<String>, l, _| types.contains(l));
+
+    let decl: Rule<String> = Rule::new(|b, _| Ok(format!("decl {} {}", b[0], b[1])));
+    decl.one(&type_name).literal(" ").one(&ident).literal(";");
+
+    let expr: Rule<String> = Rule::new(|b, _| Ok(format!("expr {}", b[0])));
+    expr.one(&ident).literal(";");
+
+    let r: Rule<String> = Rule::default();
+    r.none_or_many(Rule::default().any_of(vec![&typedef, &decl, &expr]));
+
+    let mut types: HashSet<String> = HashSet::new();
+    assert_eq!(r.scan_with_state("typedef size;size n;", &mut types).unwrap(), vec!["typedef size", "decl size n"]);
+
+    // Before its typedef the name isn't a type yet.
+    let err = r.scan_with_state("size n;typedef size;", &mut HashSet::<String>::new()).unwrap_err();
+    assert_eq!(err.msg, "Expected 'a'-'z' or \";\".");
+    assert_eq!(err.start_char, 4);
+
+    assert_eq!(r.scan("n;").unwrap_err().msg, "Rule has a state, scan it with `scan_with_state`.");
+    assert_eq!(type_name.scan("size").unwrap_err().msg, "Rule has a state, scan it with `scan_with_state`.");
+}
+
+#[test]
+fn guard_not_sync() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+
+    let r: Rule<i32> = Rule::default();
+    r.any_char().guard(move |_, _| {
+        counter.set(counter.get() + 1);
+        true
+    });
+
+    assert!(r.scan("x").is_ok());
+    assert_eq!(calls.get(), 1);
+    assert_eq!(r.freeze().err().unwrap().to_string(), "Guard is not thread safe, use `Rule::guard_sync` instead.");
+}
+
+#[test]
+fn guard_frozen() {
+    let r: Rule<i32> = Rule::default();
+    r.any_of(vec![Rule::default().any_char().guard_sync(|l, _| l == "x"), Rule::default().literal("y")]);
+
+    let frozen = r.freeze().unwrap();
+    assert!(frozen.scan("x").is_ok());
+    assert!(frozen.scan("y").is_ok());
+    assert!(frozen.scan("z").is_err());
+}
+
+#[test]
+fn guard_display() {
+    let r: Rule<i32> = Rule::default();
+    r.any_char().guard(|_, _| true);
+
+    assert_eq!(r.to_string(), ". guard(..)");
+}
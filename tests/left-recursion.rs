@@ -0,0 +1,109 @@
+use rule::Rule;
+
+fn calc(memoize: bool) -> Rule<f64> {
+    let expr: Rule<f64> = Rule::default();
+
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let num = Rule::new(|_, l| l.parse().map_err(|x| format!("{}", x)));
+    num.at_least(1, &digit);
+
+    let group = Rule::default();
+    group.literal("(").one(&expr).literal(")");
+
+    let atom = Rule::default();
+    atom.any_of(vec![&num, &group]);
+
+    let mul = Rule::default();
+    let times = Rule::new(|b, _| Ok(b[0] * b[1]));
+    times.one(&mul).literal("*").one(&atom);
+    let divide = Rule::new(|b, _| Ok(b[0] / b[1]));
+    divide.one(&mul).literal("/").one(&atom);
+    mul.any_of(vec![&times, &divide, &atom]);
+
+    let add = Rule::default();
+    let plus = Rule::new(|b, _| Ok(b[0] + b[1]));
+    plus.one(&add).literal("+").one(&mul);
+    let minus = Rule::new(|b, _| Ok(b[0] - b[1]));
+    minus.one(&add).literal("-").one(&mul);
+    add.any_of(vec![&plus, &minus, &mul]);
+
+    expr.one(&add);
+
+    if memoize {
+        mul.memoize();
+        add.memoize();
+    }
+
+    expr
+}
+
+#[test]
+fn left_recursion_direct() {
+    for memoize in [false, true] {
+        let calc = calc(memoize);
+        assert_eq!(calc.scan("10-3-2").unwrap(), vec![5f64]);
+        assert_eq!(calc.scan("64/4/2").unwrap(), vec![8f64]);
+        assert_eq!(calc.scan("2*(3+4)*5").unwrap(), vec![70f64]);
+        assert_eq!(calc.scan("1+2*3-4/2").unwrap(), vec![5f64]);
+        assert_eq!(calc.scan("((2+3*4+5))").unwrap(), vec![19f64]);
+        assert_eq!(calc.scan("7").unwrap(), vec![7f64]);
+        assert!(calc.scan("1+").is_err());
+        assert!(calc.scan("1+2)").is_err());
+    }
+}
+
+#[test]
+fn left_recursion_indirect() {
+    for memoize in [false, true] {
+        let sum: Rule<String> = Rule::default();
+
+        let num = Rule::new(|_, l| Ok(l.to_string()));
+        num.char_in('0', '9');
+
+        let lhs = Rule::default();
+        lhs.one(&sum);
+
+        let add = Rule::new(|b, _| Ok(format!("({}+{})", b[0], b[1])));
+        add.one(&lhs).literal("+").one(&num);
+
+        sum.any_of(vec![&add, &num]);
+
+        if memoize {
+            sum.memoize();
+            lhs.memoize();
+        }
+
+        assert_eq!(sum.scan("1+2+3+4").unwrap(), vec!["(((1+2)+3)+4)"]);
+        assert_eq!(sum.scan("1").unwrap(), vec!["1"]);
+        assert!(sum.scan("+1").is_err());
+    }
+}
+
+#[test]
+fn left_recursion_with_no_backtrack() {
+    for memoize in [false, true] {
+        let list: Rule<u32> = Rule::default();
+
+        let item = Rule::new(|_, _| Ok(1));
+        item.literal("x");
+
+        let more = Rule::new(|b, _| Ok(b[0] + b[1]));
+        more.one(&list).literal(",").no_backtrack("Item expected.".to_string()).one(&item);
+
+        list.any_of(vec![&more, &item]);
+
+        if memoize {
+            list.memoize();
+        }
+
+        assert_eq!(list.scan("x,x,x").unwrap(), vec![3]);
+
+        let err = list.scan("x,x,y").unwrap_err();
+        assert_eq!(format!("{}", err), "Error found at line 1, column 4: Item expected.");
+
+        let err = list.scan("x,x,x;").unwrap_err();
+        assert_eq!(format!("{}", err), "Error found at line 1, column 5: Expected \",\" or end of input.");
+    }
+}
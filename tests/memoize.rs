@@ -0,0 +1,114 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use rule::Rule;
+
+fn nested_parens(memoize: bool) -> (Rule<u32>, Rc<Cell<u32>>) {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+
+    let expr: Rule<u32> = Rule::default();
+
+    let num = Rule::new(move |_, l| {
+        counter.set(counter.get() + 1);
+        l.parse().map_err(|_| String::from("NaN"))
+    });
+    num.char_in('0', '9');
+
+    let group = Rule::default();
+    group.literal("(").one(&expr).literal(")");
+
+    let term = Rule::default();
+    term.any_of(vec![&group, &num]);
+
+    let add = Rule::new(|b, _| Ok(b[0] + b[1]));
+    add.one(&term).literal("+").one(&expr);
+
+    let sub = Rule::new(|b, _| Ok(b[0] - b[1]));
+    sub.one(&term).literal("-").one(&expr);
+
+    expr.any_of(vec![&add, &sub, &term]);
+
+    if memoize {
+        term.memoize();
+    }
+
+    (expr, calls)
+}
+
+#[test]
+fn memoize_prevents_exponential_rescanning() {
+    let code = "((((((((7))))))))";
+
+    let (expr, calls) = nested_parens(false);
+    assert_eq!(expr.scan(code).unwrap(), vec![7]);
+    assert_eq!(calls.get(), 19683);
+
+    let (expr, calls) = nested_parens(true);
+    assert_eq!(expr.scan(code).unwrap(), vec![7]);
+    assert_eq!(calls.get(), 1);
+
+    let (expr, _) = nested_parens(true);
+    assert_eq!(expr.scan("(1+(2+3))-(4)").unwrap(), vec![2]);
+}
+
+#[test]
+fn memoize_reuses_branches() {
+    let calls = Rc::new(Cell::new(0));
+    let counter = calls.clone();
+
+    let word = Rule::new(move |_, l| {
+        counter.set(counter.get() + 1);
+        Ok(l.to_string())
+    });
+    word.literal("word").memoize();
+
+    let x = Rule::new(|mut b, l| Ok(format!("{}:{}", b.remove(0), l)));
+    x.one(&word).literal("x");
+
+    let y = Rule::new(|mut b, l| Ok(format!("{}:{}", b.remove(0), l)));
+    y.one(&word).literal("y");
+
+    let root: Rule<String> = Rule::default();
+    root.any_of(vec![&x, &y]);
+
+    assert_eq!(root.scan("wordy").unwrap(), vec!["word:wordy"]);
+    assert_eq!(calls.get(), 1);
+}
+
+fn numbered_lines(memoize: bool) -> Rule<bool> {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let number = Rule::default();
+    number.no_backtrack("Number expected.".to_string()).at_least(1, &digit);
+
+    let line = Rule::default();
+    line.literal("#").one(&number).literal("\n");
+
+    let empty = Rule::default();
+    empty.literal("#").one(&number).eof();
+
+    let entry = Rule::default();
+    entry.any_of(vec![&line, &empty]);
+
+    if memoize {
+        number.memoize();
+        line.memoize();
+    }
+
+    let root: Rule<bool> = Rule::default();
+    root.none_or_many(&entry);
+    root
+}
+
+#[test]
+fn memoize_keeps_no_backtrack_errors() {
+    for code in ["#1\n#22\n#3", "#1\n#22\n#", "#1\n#\n#3", "#1\n#22#3", "#1\n#22\n3"] {
+        let expect = numbered_lines(false).scan(code).map_err(|e| format!("{}", e));
+        let result = numbered_lines(true).scan(code).map_err(|e| format!("{}", e));
+        assert_eq!(result, expect);
+    }
+
+    let err = numbered_lines(true).scan("#1\n#\n#3").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 2, column 1: Number expected.");
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants, clippy::redundant_pattern_matching)]
+
 use rule::Rule;
 
 #[test]
@@ -6,7 +8,7 @@ fn no_backtrack_1() {
     root.literal("東東").no_backtrack("Oops!".to_string()).literal("💝💝💝");
     
     if let Err(err) = root.scan("東") {
-        assert_eq!(format!("{}", err), "Error found at line 1, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 1, column 0: Expected \"東東\".".to_string());
     }
     else {
         assert!(false);
@@ -41,7 +43,7 @@ fn no_backtrack_1() {
     }
     
     if let Err(err) = root.scan("東東💝💝💝banana") {
-        assert_eq!(format!("{}", err), "Error found at line 1, column 5: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 1, column 5: Expected end of input.".to_string());
     }
     else {
         assert!(false);
@@ -64,21 +66,21 @@ fn no_backtrack_2() {
     }
     
     if let Err(err) = root.scan("東東💝💝💝\n東") {
-        assert_eq!(format!("{}", err), "Error found at line 2, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 2, column 0: Expected \"東東💝💝💝\\n\" or end of input.".to_string());
     }
     else {
         assert!(false);
     }
     
     if let Err(err) = root.scan("東東💝💝💝\n東東💝💝💝\n東東💝💝💝") {
-        assert_eq!(format!("{}", err), "Error found at line 3, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 3, column 0: Expected \"東東💝💝💝\\n\" or end of input.".to_string());
     }
     else {
         assert!(false);
     }
 
     if let Err(err) = root.scan("東東💝💝💝\n東東💝💝💝\n東東💝💝💝\n東") {
-        assert_eq!(format!("{}", err), "Error found at line 4, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 4, column 0: Expected \"東東💝💝💝\\n\" or end of input.".to_string());
     }
     else {
         assert!(false);
@@ -101,7 +103,7 @@ fn no_backtrack_3() {
     }
     
     if let Err(err) = root.scan("東東💝💝💝\n東") {
-        assert_eq!(format!("{}", err), "Error found at line 2, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 2, column 0: Expected \"東東\" or end of input.".to_string());
     }
     else {
         assert!(false);
@@ -115,7 +117,7 @@ fn no_backtrack_3() {
     }
 
     if let Err(err) = root.scan("東東💝💝💝\n東東💝💝💝\n東東💝💝💝\n東") {
-        assert_eq!(format!("{}", err), "Error found at line 4, column 0: Syntax error.".to_string());
+        assert_eq!(format!("{}", err), "Error found at line 4, column 0: Expected \"東東\" or end of input.".to_string());
     }
     else {
         assert!(false);
@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants, clippy::bool_assert_comparison, clippy::redundant_pattern_matching)]
+
 use rule::Rule;
 
 #[test]
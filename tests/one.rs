@@ -1,3 +1,5 @@
+#![allow(clippy::assertions_on_constants, clippy::needless_borrow)]
+
 use rule::Rule;
 
 #[test]
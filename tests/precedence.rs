@@ -0,0 +1,137 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::thread;
+use rule::{Assoc, GrammarError, PrecedenceBuilder, Rule};
+
+fn calc() -> Rule<f64> {
+    let expr: Rule<f64> = Rule::default();
+
+    let num = Rule::new_sync(|_, l| l.parse().map_err(|x| format!("{}", x)));
+    num.at_least(1, Rule::default().char_in('0', '9'));
+
+    let group = Rule::default();
+    group.literal("(").one(&expr).literal(")");
+
+    let atom = Rule::default();
+    atom.any_of(vec![&num, &group]);
+
+    let table = PrecedenceBuilder::new(&atom)
+        .infix_sync("+", 1, Assoc::Left, |a, _, b| Ok(a + b))
+        .infix_sync("-", 1, Assoc::Left, |a, _, b| Ok(a - b))
+        .infix_sync("*", 2, Assoc::Left, |a, _, b| Ok(a * b))
+        .infix_sync("/", 2, Assoc::Left, |a, _, b| if b == 0f64 { Err(String::from("Division by zero.")) } else { Ok(a / b) })
+        .infix_sync("^", 4, Assoc::Right, |a, _, b| Ok(a.powf(b)))
+        .infix_sync("**", 4, Assoc::Right, |a, _, b| Ok(a.powf(b)))
+        .prefix_sync("-", 3, |_, a| Ok(-a))
+        .postfix_sync("!", 5, |a, _| Ok((1..=a as u64).product::<u64>() as f64))
+        .build();
+
+    expr.one(&table);
+    expr
+}
+
+fn eval(calc: &Rule<f64>, code: &str) -> f64 {
+    calc.scan(code).unwrap()[0]
+}
+
+#[test]
+fn precedence_calc() {
+    let calc = calc();
+    assert_eq!(eval(&calc, "1+2*3"), 7f64);
+    assert_eq!(eval(&calc, "(1+2)*3"), 9f64);
+    assert_eq!(eval(&calc, "10-4-3"), 3f64);
+    assert_eq!(eval(&calc, "64/4/2"), 8f64);
+    assert_eq!(eval(&calc, "2^3^2"), 512f64);
+    assert_eq!(eval(&calc, "2**3**2"), 512f64);
+    assert_eq!(eval(&calc, "-2^2"), -4f64);
+    assert_eq!(eval(&calc, "-2*3"), -6f64);
+    assert_eq!(eval(&calc, "--2"), 2f64);
+    assert_eq!(eval(&calc, "3!*2"), 12f64);
+    assert_eq!(eval(&calc, "-3!"), -6f64);
+    assert_eq!(eval(&calc, "2*(3+4)*5+0"), 70f64);
+}
+
+#[test]
+fn precedence_errors() {
+    let calc = calc();
+    assert!(calc.scan("").is_err());
+    assert!(calc.scan("1+").is_err());
+    assert!(calc.scan("*1").is_err());
+
+    let err = calc.scan("1+(2-3").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 6: Expected '0'-'9', \"**\", \"*\", \"/\", \"^\", \"!\", \"+\", \"-\" or \")\".");
+
+    let err = calc.scan("2+4/(1-1)").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 3: Division by zero.");
+}
+
+#[test]
+fn precedence_op_text() {
+    let num = Rule::new_sync(|_, l| Ok(l.to_string()));
+    num.char_in('a', 'z');
+
+    let fold = |a: String, op: &str, b: String| Ok(format!("({}{}{})", a, op, b));
+
+    let expr = PrecedenceBuilder::new(&num)
+        .infix("<", 1, Assoc::Left, fold)
+        .infix("<=", 1, Assoc::Left, fold)
+        .infix("=", 0, Assoc::Right, fold)
+        .prefix("!", 2, |op, a| Ok(format!("{}{}", op, a)))
+        .build();
+
+    assert_eq!(expr.scan("a=b=c<=!d<e").unwrap(), vec!["(a=(b=((c<=!d)<e)))"]);
+    assert_eq!(format!("{}", expr), "precedence([a-z], infix \"<=\" 1 left, infix \"<\" 1 left, infix \"=\" 0 right, prefix \"!\" 2)");
+}
+
+#[test]
+fn precedence_frozen() {
+    let frozen = calc().freeze().unwrap();
+
+    thread::scope(|s| {
+        s.spawn(|| assert_eq!(frozen.scan("(1+2)*3!").unwrap(), vec![18f64]));
+        s.spawn(|| assert_eq!(frozen.scan("2^-1").unwrap(), vec![0.5f64]));
+    });
+}
+
+#[test]
+fn precedence_not_sync() {
+    let num = Rule::new(|_, l| l.parse().map_err(|_| String::from("NaN")));
+    num.char_in('0', '9');
+
+    // The folds don't have to be thread safe, but then the rule can't be frozen.
+    let folds = Rc::new(Cell::new(0));
+    let counter = folds.clone();
+
+    let sum: Rule<i32> = PrecedenceBuilder::new(&num)
+        .infix("+", 1, Assoc::Left, move |a, _, b| {
+            counter.set(counter.get() + 1);
+            Ok(a + b)
+        })
+        .build();
+
+    assert_eq!(sum.scan("1+2+3").unwrap(), vec![6]);
+    assert_eq!(folds.get(), 2);
+    assert!(sum.freeze().is_err());
+}
+
+#[test]
+fn precedence_empty_operator() {
+    let num: Rule<i32> = Rule::default();
+    num.char_in('0', '9');
+
+    let result = PrecedenceBuilder::new(&num).infix("", 1, Assoc::Left, |a, _, b| Ok(a + b)).try_build();
+
+    if let Err(GrammarError::Invalid(msg)) = result {
+        assert_eq!(msg, "Operator is empty.");
+    }
+    else {
+        panic!("Expected an invalid grammar error.");
+    }
+}
+
+#[test]
+#[should_panic(expected = "Operator is empty.")]
+fn precedence_empty_operator_panics() {
+    let num: Rule<i32> = Rule::default();
+    PrecedenceBuilder::new(&num).prefix("", 1, |_, a| Ok(-a)).build();
+}
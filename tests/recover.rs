@@ -0,0 +1,89 @@
+use rule::Rule;
+
+// Statements like `let a = 1;`, each on its own line.
+fn program(memoize: bool) -> Rule<String> {
+    let space = Rule::default();
+    space.literal(" ");
+
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let ident = Rule::new(|_, l| Ok(l.to_string()));
+    ident.at_least(1, &letter);
+
+    let num = Rule::new(|_, l| Ok(l.to_string()));
+    num.at_least(1, &digit);
+
+    let semicolon = Rule::default();
+    semicolon.literal(";");
+
+    let sync = Rule::default();
+    sync.any_of(vec![&semicolon, Rule::default().literal("\n")]);
+
+    let stmt = Rule::new(|b, _| Ok(format!("{}={}", b[0], b[1])));
+    stmt.literal("let").at_least(1, &space)
+        .no_backtrack(String::from("Name expected.")).one(&ident).none_or_many(&space)
+        .no_backtrack(String::from("\"=\" expected.")).literal("=").none_or_many(&space)
+        .no_backtrack(String::from("Value expected.")).one(&num)
+        .no_backtrack(String::from("\";\" expected.")).literal(";")
+        .recover_to(&sync);
+
+    let line = Rule::default();
+    line.one(&stmt).none_or_many(Rule::default().literal("\n"));
+
+    if memoize {
+        stmt.memoize();
+    }
+
+    let program = Rule::default();
+    program.none_or_many(&line);
+    program
+}
+
+#[test]
+fn recover_collects_errors() {
+    for memoize in [false, true] {
+        let (result, errors) = program(memoize).scan_recover("let a = 1;\nlet b = ;\nlet c 3;\nlet d = 4;");
+        assert_eq!(result, Some(vec![String::from("a=1"), String::from("d=4")]));
+
+        let errors: Vec<String> = errors.iter().map(|e| format!("{}", e)).collect();
+        assert_eq!(errors, vec![
+            "Error found at line 2, column 8: Value expected.",
+            "Error found at line 3, column 6: \"=\" expected.",
+        ]);
+    }
+}
+
+#[test]
+fn recover_and_fail() {
+    let (result, errors) = program(false).scan_recover("let a = ;\nlet b = 2;\n!");
+    assert!(result.is_none());
+
+    let errors: Vec<String> = errors.iter().map(|e| format!("{}", e)).collect();
+    assert_eq!(errors, vec![
+        "Error found at line 1, column 8: Value expected.",
+        "Error found at line 3, column 0: Expected \"\\n\", \"let\" or end of input.",
+    ]);
+}
+
+#[test]
+fn recover_scan_reports_first_error() {
+    let program = program(false);
+    assert_eq!(program.scan("let a = 1;").unwrap(), vec!["a=1"]);
+
+    let err = program.scan("let a = 1;\nlet b = ;\nlet c 3;").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 2, column 8: Value expected.");
+
+    let frozen: Rule<u32> = Rule::default();
+    frozen.literal("x").no_backtrack(String::from("Dot expected.")).literal(".").recover_to(Rule::default().literal("."));
+    let many: Rule<u32> = Rule::default();
+    many.none_or_many(&frozen);
+
+    let (result, errors) = many.freeze().unwrap().scan_recover("x.xx.x.");
+    assert_eq!(result, Some(vec![]));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].col, 3);
+}
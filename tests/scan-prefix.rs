@@ -0,0 +1,63 @@
+use rule::Rule;
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Num(u32),
+    Word(String),
+    Space,
+}
+
+#[test]
+fn scan_prefix() {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let num: Rule<u32> = Rule::new(|_, l| l.parse().map_err(|_| String::from("NaN")));
+    num.at_least(1, &digit);
+
+    assert_eq!(num.scan_prefix("123abc").unwrap(), (vec![123], 3, 3));
+    assert_eq!(num.scan_prefix("42").unwrap(), (vec![42], 2, 2));
+    assert!(num.scan_prefix("abc").is_err());
+    assert!(num.scan("123abc").is_err());
+}
+
+#[test]
+fn scan_prefix_lexer() {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let num = Rule::new(|_, l| l.parse().map(Token::Num).map_err(|_| String::from("NaN")));
+    num.at_least(1, &digit);
+
+    let letter = Rule::default();
+    letter.any_char_except(vec![' ', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9']);
+
+    let word = Rule::new(|_, l| Ok(Token::Word(l.to_string())));
+    word.at_least(1, &letter);
+
+    let space = Rule::new(|_, _| Ok(Token::Space));
+    space.literal(" ");
+
+    let token: Rule<Token> = Rule::default();
+    token.any_of(vec![&num, &word, &space]);
+
+    let mut code = "東京 12 ünï";
+    let mut tokens = Vec::new();
+    let mut chars = 0;
+
+    while !code.is_empty() {
+        let (mut branches, bytes, char_count) = token.scan_prefix(code).unwrap();
+        tokens.append(&mut branches);
+        chars += char_count;
+        code = &code[bytes..];
+    }
+
+    assert_eq!(chars, 9);
+    assert_eq!(tokens, vec![
+        Token::Word(String::from("東京")),
+        Token::Space,
+        Token::Num(12),
+        Token::Space,
+        Token::Word(String::from("ünï")),
+    ]);
+}
@@ -0,0 +1,78 @@
+use rule::{Rule, SepOptions};
+
+fn num() -> Rule<String> {
+    let num = Rule::new(|_, l| Ok(l.to_string()));
+    num.at_least(1, Rule::default().char_in('0', '9'));
+    num
+}
+
+fn comma() -> Rule<String> {
+    let comma = Rule::new(|_, l| Ok(l.to_string()));
+    comma.literal(",");
+    comma
+}
+
+#[test]
+fn sep_by_list() {
+    let list: Rule<String> = Rule::default();
+    list.literal("(").sep_by(0, u64::MAX, &num(), &comma()).literal(")");
+
+    assert_eq!(list.scan("()").unwrap().len(), 0);
+    assert_eq!(list.scan("(1)").unwrap(), vec!["1"]);
+    assert_eq!(list.scan("(1,22,333)").unwrap(), vec!["1", "22", "333"]);
+    assert!(list.scan("(1,)").is_err());
+    assert!(list.scan("(,1)").is_err());
+    assert!(list.scan("(1,,2)").is_err());
+}
+
+#[test]
+fn sep_by_min_max() {
+    let list: Rule<String> = Rule::default();
+    list.sep_by(2, 3, &num(), &comma());
+
+    assert!(list.scan("1").is_err());
+    assert_eq!(list.scan("1,2").unwrap(), vec!["1", "2"]);
+    assert_eq!(list.scan("1,2,3").unwrap(), vec!["1", "2", "3"]);
+    assert!(list.scan("1,2,3,4").is_err());
+
+    let err = list.scan("1,2,3,4").unwrap_err();
+    assert_eq!(err.msg, "Expected '0'-'9' or end of input.");
+}
+
+#[test]
+fn sep_by_trailing() {
+    let options = SepOptions { trailing: true, ..SepOptions::default() };
+
+    let list: Rule<String> = Rule::default();
+    list.literal("[").sep_by_with(0, 2, &num(), &comma(), options).literal("]");
+
+    assert_eq!(list.scan("[]").unwrap().len(), 0);
+    assert_eq!(list.scan("[1,2]").unwrap(), vec!["1", "2"]);
+    assert_eq!(list.scan("[1,2,]").unwrap(), vec!["1", "2"]);
+    assert_eq!(list.scan("[1,]").unwrap(), vec!["1"]);
+    assert!(list.scan("[,]").is_err());
+    assert!(list.scan("[1,,]").is_err());
+    assert!(list.scan("[1,2,3]").is_err());
+}
+
+#[test]
+fn sep_by_keep_sep() {
+    let options = SepOptions { keep_sep: true, trailing: true };
+
+    let list: Rule<String> = Rule::default();
+    list.sep_by_with(1, u64::MAX, &num(), &comma(), options);
+
+    assert_eq!(list.scan("1,2,3,").unwrap(), vec!["1", ",", "2", ",", "3", ","]);
+}
+
+#[test]
+fn sep_by_display() {
+    let num = num();
+    num.set_name("num");
+
+    let list: Rule<String> = Rule::default();
+    list.sep_by(1, u64::MAX, &num, Rule::default().literal(","));
+    list.sep_by_with(0, 1, &num, Rule::default().literal(";"), SepOptions { trailing: true, ..SepOptions::default() });
+
+    assert_eq!(format!("{}", list), "sep_by(num, \",\")+ sep_by(num, \";\", trailing)?\nnum = [0-9]+");
+}
@@ -0,0 +1,59 @@
+#![allow(clippy::assertions_on_constants)]
+
+use rule::{Rule, Span};
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Word(String, Span),
+    List(Vec<Node>, Span),
+}
+
+fn list() -> Rule<Node> {
+    let letter = Rule::default();
+    letter.any_char_except(vec![' ', '\r', '\n', '(', ')']);
+
+    let word = Rule::with_span(|_, l, span| Ok(Node::Word(l.to_string(), span)));
+    word.at_least(1, &letter);
+
+    let space = Rule::default();
+    space.any_of(vec![Rule::default().literal(" "), Rule::default().literal("\r\n"), Rule::default().literal("\n")]);
+
+    let list = Rule::with_span(|b, _, span| Ok(Node::List(b, span)));
+    let item = Rule::default();
+    item.any_of(vec![&word, &list]);
+    list.literal("(").none_or_many(&space).none_or_many(Rule::default().one(&item).none_or_many(&space)).literal(")");
+    list
+}
+
+#[test]
+fn span_of_branches() {
+    let span = |start_byte, end_byte, start_char, end_char, line, col| Span { col, end_byte, end_char, line, start_byte, start_char };
+
+    if let Ok(mut nodes) = list().scan("(añb\r\n  (c))") {
+        assert_eq!(nodes.pop().unwrap(), Node::List(vec![
+            Node::Word(String::from("añb"), span(1, 5, 1, 4, 1, 1)),
+            Node::List(vec![
+                Node::Word(String::from("c"), span(10, 11, 9, 10, 2, 3)),
+            ], span(9, 12, 8, 11, 2, 2)),
+        ], span(0, 13, 0, 12, 1, 0)));
+    }
+    else {
+        assert!(false);
+    }
+}
+
+#[test]
+fn span_with_frozen_rule() {
+    let num = Rule::with_span_sync(|_, _, span| Ok(span.start_char));
+    num.char_in('0', '9');
+
+    let nums: Rule<usize> = Rule::default();
+    nums.at_least(1, Rule::default().maybe(Rule::default().literal(",")).one(&num));
+
+    let frozen = nums.freeze().unwrap();
+    assert_eq!(frozen.scan("1,2,3").unwrap(), vec![0, 2, 4]);
+
+    let word: Rule<usize> = Rule::with_span(|_, _, span| Ok(span.start_char));
+    word.literal("word");
+    assert!(word.freeze().is_err());
+}
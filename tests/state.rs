@@ -0,0 +1,169 @@
+use rule::Rule;
+use std::collections::HashSet;
+
+#[test]
+fn state_count() {
+    let digit: Rule<u32> = Rule::with_state(|count: &mut usize, _, l| {
+        *count += 1;
+        Ok(l.parse().unwrap())
+    });
+    digit.char_in('0', '9');
+
+    let r: Rule<u32> = Rule::new(|b, _| Ok(b.iter().sum()));
+    r.at_least(1, &digit);
+
+    let mut count = 0usize;
+    assert_eq!(r.scan_with_state("1234", &mut count).unwrap(), vec![10]);
+    assert_eq!(count, 4);
+
+    // The rules don't hold the state, so they can scan again with another one.
+    let mut count = 100usize;
+    assert_eq!(r.scan_with_state("56", &mut count).unwrap(), vec![11]);
+    assert_eq!(count, 102);
+}
+
+#[test]
+fn state_rollback() {
+    let a: Rule<i32> = Rule::with_state(|seen: &mut Vec<String>, _, l| {
+        seen.push(l.to_string());
+        Ok(0)
+    });
+    a.literal("a");
+
+    let ab = Rule::default();
+    ab.one(&a).literal("b");
+
+    let ac = Rule::default();
+    ac.one(&a).literal("c");
+
+    let r: Rule<i32> = Rule::default();
+    r.any_of(vec![&ab, &ac]).eof();
+
+    // The first alternative saw the "a" too, but it failed.
+    let mut seen: Vec<String> = Vec::new();
+    r.scan_with_state("ac", &mut seen).unwrap();
+    assert_eq!(seen, vec!["a"]);
+}
+
+#[test]
+fn state_rollback_on_error() {
+    let a: Rule<i32> = Rule::with_state(|count: &mut usize, _, _| {
+        *count += 1;
+        Ok(0)
+    });
+    a.literal("a");
+
+    let r: Rule<i32> = Rule::default();
+    r.at_least(1, &a).literal("!");
+
+    let mut count = 0usize;
+    assert!(r.scan_with_state("aaa?", &mut count).is_err());
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn state_lookahead() {
+    let a: Rule<i32> = Rule::with_state(|count: &mut usize, _, _| {
+        *count += 1;
+        Ok(0)
+    });
+    a.literal("a");
+
+    let r: Rule<i32> = Rule::default();
+    r.and(&a).not(Rule::default().one(&a).literal("b")).one(&a);
+
+    let mut count = 0usize;
+    r.scan_with_state("a", &mut count).unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn state_memoize() {
+    let item: Rule<i32> = Rule::with_state(|count: &mut usize, _, _| {
+        *count += 1;
+        Ok(0)
+    });
+    item.literal("x").memoize();
+
+    let r: Rule<i32> = Rule::default();
+    r.any_of(vec![Rule::default().one(&item).literal("!"), Rule::default().one(&item).literal("?")]);
+
+    let mut count = 0usize;
+    r.scan_with_state("x?", &mut count).unwrap();
+    assert_eq!(count, 1);
+}
+
+fn sum(memoize: bool) -> Rule<i32> {
+    let num: Rule<i32> = Rule::with_state(|seen: &mut Vec<String>, _, l| {
+        seen.push(format!("num {}", l));
+        Ok(0)
+    });
+    num.char_in('0', '9');
+
+    let sum: Rule<i32> = Rule::default();
+
+    let add: Rule<i32> = Rule::with_state(|seen: &mut Vec<String>, _, l| {
+        seen.push(format!("add {}", l));
+        Ok(0)
+    });
+    add.one(&sum).literal("+").one(&num);
+    sum.any_of(vec![&add, &num]);
+
+    if memoize {
+        sum.memoize();
+    }
+
+    sum
+}
+
+#[test]
+fn state_left_recursion() {
+    // Every time the seed grows it's scanned again, the changes of the seeds before are rolled back.
+    for memoize in [false, true] {
+        let mut seen: Vec<String> = Vec::new();
+        sum(memoize).scan_with_state("1+2+3", &mut seen).unwrap();
+        assert_eq!(seen, vec!["num 1", "num 2", "add 1+2", "num 3", "add 1+2+3"]);
+    }
+}
+
+#[test]
+fn state_symbol_table() {
+    let name: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    name.at_least(1, Rule::default().char_in('a', 'z'));
+
+    let decl: Rule<String> = Rule::with_state(|names: &mut HashSet<String>, b: Vec<String>, _| {
+        names.insert(b[0].clone());
+        Ok(String::new())
+    });
+    decl.literal("let ").one(&name).literal(";");
+
+    let usage: Rule<String> = Rule::with_state(|names: &mut HashSet<String>, b: Vec<String>, _| {
+        match names.contains(&b[0]) {
+            true => Ok(b[0].clone()),
+            false => Err(format!("{} is not declared.", b[0])),
+        }
+    });
+    usage.one(&name).literal(";");
+
+    let stmt: Rule<String> = Rule::default();
+    stmt.any_of(vec![&decl, &usage]);
+
+    let r: Rule<String> = Rule::default();
+    r.none_or_many(&stmt);
+
+    let mut names: HashSet<String> = HashSet::new();
+    assert_eq!(r.scan_with_state("let x;x;", &mut names).unwrap(), vec!["", "x"]);
+
+    let err = r.scan_with_state("let x;y;", &mut HashSet::<String>::new()).unwrap_err();
+    assert_eq!(err.msg, "y is not declared.");
+}
+
+#[test]
+fn state_without_state() {
+    let r: Rule<i32> = Rule::with_state(|_: &mut usize, _, _| Ok(0));
+    r.literal("a");
+
+    assert_eq!(r.scan("a").unwrap_err().msg, "Rule has a state, scan it with `scan_with_state`.");
+    assert_eq!(r.scan_with_state("a", &mut 'x').unwrap_err().msg, "State has another type than the rule expects.");
+    assert!(r.freeze().is_err());
+}
@@ -0,0 +1,175 @@
+use rule::{Rule, Token};
+use std::ops::Range;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Ident,
+    Num,
+    Plus,
+    Semi,
+}
+
+#[derive(Clone, Debug)]
+struct Tok {
+    kind: Kind,
+    span: Range<usize>,
+}
+
+impl Token for Tok {
+    type Kind = Kind;
+
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+}
+
+fn lex(source: &str) -> Vec<Tok> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            '+' => Kind::Plus,
+            ';' => Kind::Semi,
+            '0'..='9' => Kind::Num,
+            c if c.is_alphabetic() => Kind::Ident,
+            _ => continue,
+        };
+
+        let mut end = start + c.len_utf8();
+
+        if kind == Kind::Num || kind == Kind::Ident {
+            while let Some(&(i, n)) = chars.peek() {
+                if !n.is_alphanumeric() {
+                    break;
+                }
+
+                end = i + n.len_utf8();
+                chars.next();
+            }
+        }
+
+        tokens.push(Tok { kind, span: start..end });
+    }
+
+    tokens
+}
+
+#[test]
+fn tokens_kind() {
+    let source = "12 + 30 + 0";
+
+    let num: Rule<i32, Tok> = Rule::new(|_, l| Ok(l.parse().unwrap()));
+    num.token(Kind::Num);
+
+    let r: Rule<i32, Tok> = Rule::new(|b, _| Ok(b.iter().sum()));
+    r.one(&num).none_or_many(Rule::default().token(Kind::Plus).one(&num));
+
+    assert_eq!(r.scan_tokens(&lex(source), source).unwrap(), vec![42]);
+}
+
+#[test]
+fn tokens_if() {
+    let source = "let x; var y;";
+
+    let keyword: Rule<String, Tok> = Rule::new(|_, l| Ok(l.to_uppercase()));
+    keyword.token_if(|t| t.kind == Kind::Ident && t.span.len() == 3);
+
+    let ident: Rule<String, Tok> = Rule::new(|_, l| Ok(l.to_string()));
+    ident.token(Kind::Ident);
+
+    let stmt: Rule<String, Tok> = Rule::default();
+    stmt.one(&keyword).one(&ident).token(Kind::Semi);
+
+    let r: Rule<String, Tok> = Rule::default();
+    r.at_least(1, &stmt);
+
+    assert_eq!(r.scan_tokens(&lex(source), source).unwrap(), vec!["LET", "x", "VAR", "y"]);
+}
+
+#[test]
+fn tokens_lexeme() {
+    let source = "a + bc + d";
+
+    let r: Rule<String, Tok> = Rule::new(|_, l| Ok(l.to_string()));
+    r.sep_by(1, u64::MAX, Rule::default().token(Kind::Ident), Rule::default().token(Kind::Plus));
+
+    // The lexeme is the text of the tokens, the whitespace between them is not part of a token.
+    assert_eq!(r.scan_tokens(&lex(source), source).unwrap(), vec!["a+bc+d"]);
+}
+
+#[test]
+fn tokens_span() {
+    let source = "x;\n  foo + 12;";
+
+    let sum: Rule<(usize, usize, usize, usize), Tok> = Rule::with_span(|_, _, span| Ok((span.start_byte, span.end_byte, span.line, span.col)));
+    sum.token(Kind::Ident).token(Kind::Plus).token(Kind::Num);
+
+    let r: Rule<(usize, usize, usize, usize), Tok> = Rule::default();
+    r.token(Kind::Ident).token(Kind::Semi).one(&sum).token(Kind::Semi);
+
+    assert_eq!(r.scan_tokens(&lex(source), source).unwrap(), vec![(5, 13, 2, 2)]);
+}
+
+#[test]
+fn tokens_error() {
+    let source = "a + b\n+ + c";
+
+    let r: Rule<i32, Tok> = Rule::default();
+    r.sep_by(1, u64::MAX, Rule::default().token(Kind::Ident), Rule::default().token(Kind::Plus)).eof();
+
+    let err = r.scan_tokens(&lex(source), source).unwrap_err();
+    assert_eq!(err.token, Some(4));
+    assert_eq!((err.line, err.col), (2, 2));
+    assert_eq!((err.start_byte, err.end), (8, Some((9, 9))));
+    assert_eq!(err.expected, vec!["Ident"]);
+}
+
+#[test]
+fn tokens_error_at_end() {
+    let source = "a +";
+
+    let r: Rule<i32, Tok> = Rule::default();
+    r.token(Kind::Ident).token(Kind::Plus).token(Kind::Ident);
+
+    let err = r.scan_tokens(&lex(source), source).unwrap_err();
+    assert_eq!(err.token, Some(2));
+    assert_eq!((err.line, err.col), (1, 3));
+    assert_eq!(err.end, None);
+}
+
+#[test]
+fn tokens_any() {
+    let source = "a + ;";
+
+    // Any token is matched by `any_char`.
+    let r: Rule<String, Tok> = Rule::new(|_, l| Ok(l.to_string()));
+    r.any_char().any_char().any_char().eof();
+
+    assert_eq!(r.scan_tokens(&lex(source), source).unwrap(), vec!["a+;"]);
+}
+
+#[test]
+fn tokens_freeze() {
+    let source = "1 + 2";
+
+    let num: Rule<i32, Tok> = Rule::new_sync(|_, l| Ok(l.parse().unwrap()));
+    num.token(Kind::Num);
+
+    let r: Rule<i32, Tok> = Rule::new_sync(|b, _| Ok(b.iter().sum()));
+    r.one(&num).token(Kind::Plus).one(&num);
+
+    assert_eq!(r.freeze().unwrap().scan_tokens(&lex(source), source).unwrap(), vec![3]);
+}
+
+#[test]
+fn tokens_display() {
+    let r: Rule<i32, Tok> = Rule::default();
+    r.token(Kind::Ident).maybe(Rule::default().token_if(|t| t.kind == Kind::Semi));
+
+    assert_eq!(r.to_string(), "token(Ident) token_if(..)?");
+}
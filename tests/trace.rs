@@ -0,0 +1,73 @@
+use rule::{Rule, RuleInfo, ScanObserver, TracePrinter};
+
+fn greeting() -> Rule<bool> {
+    let letter = Rule::default();
+    letter.char_in('a', 'z');
+
+    let name = Rule::default();
+    name.set_name("name").at_least(1, &letter);
+
+    let root = Rule::default();
+    root.set_name("greeting").literal("hi ").one(&name);
+    root
+}
+
+#[derive(Default)]
+struct Counter {
+    enter: usize,
+    fail: Vec<(Option<String>, usize)>,
+    leaves: usize,
+    success: Vec<(Option<String>, usize, usize)>,
+}
+
+impl ScanObserver for Counter {
+    fn enter(&mut self, _: &RuleInfo, _: usize) {
+        self.enter += 1;
+    }
+
+    fn success(&mut self, rule: &RuleInfo, start: usize, end: usize) {
+        self.success.push((rule.name.map(String::from), start, end));
+    }
+
+    fn fail(&mut self, rule: &RuleInfo, index: usize) {
+        self.fail.push((rule.name.map(String::from), index));
+    }
+
+    fn leaf(&mut self, _: &str, _: usize, _: Option<usize>) {
+        self.leaves += 1;
+    }
+}
+
+#[test]
+fn trace_observer() {
+    let mut counter = Counter::default();
+    assert!(greeting().scan_traced("hi bob", &mut counter).is_ok());
+    assert_eq!(counter.enter, counter.success.len() + counter.fail.len());
+    assert_eq!(counter.leaves, 5);
+    assert_eq!(counter.fail, vec![(None, 6)]);
+    assert_eq!(counter.success.last(), Some(&(Some(String::from("greeting")), 0, 6)));
+    assert!(counter.success.contains(&(Some(String::from("name")), 3, 6)));
+}
+
+#[test]
+fn trace_printer() {
+    let mut printer = TracePrinter::new(Vec::new());
+    assert!(greeting().scan_traced("hi b1", &mut printer).is_err());
+
+    let trace = String::from_utf8(printer.into_inner()).unwrap();
+    assert_eq!(trace, "greeting @0\n\
+        \x20 \"hi \" matched @0..3\n\
+        \x20 name @3\n\
+        \x20   'a'-'z' matched @3..4\n\
+        \x20   'a'-'z' failed @4\n\
+        \x20 name matched @3..4\n\
+        greeting matched @0..4\n");
+}
+
+#[test]
+fn trace_frozen() {
+    let mut counter = Counter::default();
+    let frozen = greeting().freeze().unwrap();
+    assert!(frozen.scan_traced("hi x", &mut counter).is_ok());
+    assert_eq!(counter.success.last(), Some(&(Some(String::from("greeting")), 0, 4)));
+}
@@ -0,0 +1,79 @@
+use rule::{Grammar, Rule};
+
+fn ws() -> Rule<String> {
+    let comment = Rule::default();
+    comment.literal("//").none_or_many(Rule::default().any_char_except(vec!['\n']));
+
+    let ws = Rule::default();
+    ws.any_of(vec![Rule::default().literal(" "), Rule::default().literal("\n"), &comment]);
+    ws
+}
+
+fn list() -> Rule<String> {
+    let ident = Rule::new(|_, l| Ok(l.to_string()));
+    ident.at_least(1, Rule::default().char_in('a', 'z')).lexical();
+
+    let list = Rule::new(|b, l| Ok(format!("{}:{}", b.join(","), l)));
+    list.literal("[").sep_by(0, u64::MAX, &ident, Rule::default().literal(",")).literal("]").trivia(&ws());
+    list
+}
+
+#[test]
+fn trivia_skipped() {
+    let list = list();
+    assert_eq!(list.scan("[a,bc]").unwrap(), vec!["a,bc:[a,bc]"]);
+    assert_eq!(list.scan("  [ a ,\n bc // comment\n ] ").unwrap(), vec!["a,bc:[a,bc]"]);
+    assert_eq!(list.scan("[ ]").unwrap(), vec![":[]"]);
+}
+
+#[test]
+fn trivia_not_in_lexical() {
+    let list = list();
+    assert!(list.scan("[a b]").is_err());
+
+    let err = list.scan("[a b]").unwrap_err();
+    assert_eq!(format!("{}", err), "Error found at line 1, column 3: Expected \",\" or \"]\".");
+}
+
+#[test]
+fn trivia_without_mode() {
+    let ident = Rule::default();
+    ident.at_least(1, Rule::default().char_in('a', 'z'));
+
+    let root: Rule<String> = Rule::default();
+    root.one(&ident).literal("=").one(&ident);
+
+    assert!(root.scan("a=b").is_ok());
+    assert!(root.scan("a = b").is_err());
+}
+
+#[test]
+fn trivia_grammar() {
+    let mut grammar: Grammar<i64> = Grammar::new();
+    grammar.add("num", "'-'? [0-9]+", Some(Box::new(|_, l| l.parse().map_err(|x| format!("{}", x))))).unwrap();
+    grammar.add("sum", "num ('+' num)*", Some(Box::new(|b, _| Ok(b.iter().sum())))).unwrap();
+    grammar.set_trivia("' ' | '\\t' | '#' [^\\n]* | '\\n'").unwrap();
+    grammar.set_lexical("num").unwrap();
+
+    assert_eq!(grammar.scan("sum", "1+2").unwrap(), vec![3]);
+    assert_eq!(grammar.scan("sum", " 1 +\t-2 # three\n+ 3 ").unwrap(), vec![2]);
+    assert!(grammar.scan("sum", "1 + 2 3").is_err());
+    assert!(grammar.scan("sum", "- 1").is_err());
+}
+
+#[test]
+fn trivia_frozen() {
+    let frozen = {
+        let ident = Rule::new_sync(|_, l| Ok(l.to_string()));
+        ident.at_least(1, Rule::default().char_in('a', 'z')).lexical();
+
+        let ws = Rule::default();
+        ws.literal(" ");
+
+        let root = Rule::default();
+        root.at_least(1, &ident).trivia(&ws);
+        root.freeze().unwrap()
+    };
+
+    assert_eq!(frozen.scan(" foo bar  baz ").unwrap(), vec!["foo", "bar", "baz"]);
+}
@@ -0,0 +1,94 @@
+use rule::{GrammarError, Rule};
+
+#[test]
+fn try_builders_ok() {
+    let r: Rule<String> = Rule::new(|_, l| Ok(l.to_string()));
+    r.try_literal("a").unwrap()
+        .try_literal_ci("b").unwrap()
+        .try_alter(vec![("c", "C")]).unwrap()
+        .try_any_char_except(vec!['x']).unwrap()
+        .try_char_set_expr("[0-9]").unwrap();
+
+    assert_eq!(r.scan("aBcd5").unwrap(), vec!["aBCd5"]);
+}
+
+#[test]
+fn try_builders_invalid() {
+    let r: Rule<i32> = Rule::default();
+
+    let errors = [
+        r.try_literal("").unwrap_err(),
+        r.try_literal_string(String::new()).unwrap_err(),
+        r.try_alter(vec![]).unwrap_err(),
+        r.try_alter_string(vec![(String::new(), String::new())]).unwrap_err(),
+        r.try_alter(vec![("", "x"), ("a", "b")]).unwrap_err(),
+        r.try_alter_ci(vec![("a", ""), ("", "x")]).unwrap_err(),
+        r.try_any_char_except(vec![]).unwrap_err(),
+        r.try_any_of(vec![]).unwrap_err(),
+        r.try_set_name("").unwrap_err(),
+        r.try_char_set(vec![('z', 'a')]).unwrap_err(),
+    ];
+
+    let msgs: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+    assert_eq!(msgs, vec![
+        "Literal text must at least 1 character long.",
+        "Literal text must at least 1 character long.",
+        "List is empty.",
+        "The strings to find must be minimal one character long.",
+        "The strings to find must be minimal one character long.",
+        "The strings to find must be minimal one character long.",
+        "List of excluded characters is empty.",
+        "You must specify rules.",
+        "Name must at least 1 character long.",
+        "Invalid character range.",
+    ]);
+
+    // Nothing was added to the rule.
+    assert!(matches!(r.try_scan(""), Err(GrammarError::Undefined(None))));
+}
+
+#[test]
+#[should_panic(expected = "List is empty.")]
+fn try_panicking_builder() {
+    let r: Rule<i32> = Rule::default();
+    r.alter(vec![]);
+}
+
+#[test]
+fn try_scan_undefined() {
+    let value: Rule<i32> = Rule::default();
+    value.set_name("value");
+
+    let r: Rule<i32> = Rule::default();
+    r.literal("(").one(&value).literal(")");
+
+    let err = r.try_scan("()").unwrap_err();
+    assert!(matches!(err, GrammarError::Undefined(Some(ref name)) if name == "value"));
+    assert_eq!(err.to_string(), "Rule \"value\" is not defined.");
+}
+
+#[test]
+fn try_scan_error() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("a");
+
+    match r.try_scan("b") {
+        Err(GrammarError::Scan(err)) => assert_eq!(err.expected, vec!["\"a\""]),
+        _ => panic!("Expected a scan error."),
+    }
+
+    assert!(r.try_scan("a").is_ok());
+}
+
+#[test]
+fn try_scan_frozen() {
+    let value: Rule<i32> = Rule::default();
+    value.set_name("value");
+
+    let r: Rule<i32> = Rule::default();
+    r.one(&value);
+
+    let frozen = r.freeze().unwrap();
+    assert!(matches!(frozen.try_scan("x"), Err(GrammarError::Undefined(Some(ref name))) if name == "value"));
+}
@@ -0,0 +1,168 @@
+use rule::{GrammarIssue, Rule};
+
+#[test]
+fn validate_ok() {
+    let digit = Rule::default();
+    digit.char_in('0', '9');
+
+    let r: Rule<i32> = Rule::default();
+    r.at_least(1, &digit).maybe(Rule::default().literal(".").at_least(1, &digit)).eof();
+
+    assert!(r.validate().is_empty());
+}
+
+#[test]
+fn validate_empty_rule() {
+    let value = Rule::default();
+    value.set_name("value");
+
+    let r: Rule<i32> = Rule::default();
+    r.literal("x").one(&value);
+
+    assert_eq!(r.validate(), vec![GrammarIssue::EmptyRule(String::from("value"))]);
+}
+
+#[test]
+fn validate_empty_rule_repeated() {
+    let value = Rule::default();
+    value.set_name("value");
+
+    let r: Rule<i32> = Rule::default();
+    r.literal("x").none_or_many(&value);
+
+    // A nested empty rule matches without consuming input, so repeating it is reported too.
+    assert!(r.scan("x").is_ok());
+    assert_eq!(r.validate(), vec![
+        GrammarIssue::NullableRepetition(String::from("\"x\" value*")),
+        GrammarIssue::EmptyRule(String::from("value")),
+    ]);
+}
+
+#[test]
+fn validate_nullable_repetition() {
+    let space = Rule::default();
+    space.none_or_many(Rule::default().literal(" "));
+
+    let r: Rule<i32> = Rule::named("list", |_, _| Ok(0));
+    r.none_or_many(&space).literal("x");
+
+    // A single optional item is fine, only a repetition can loop without consuming anything.
+    let ok: Rule<i32> = Rule::default();
+    ok.maybe(&space).literal("x");
+
+    assert_eq!(r.validate(), vec![GrammarIssue::NullableRepetition(String::from("list"))]);
+    assert!(ok.validate().is_empty());
+}
+
+#[test]
+fn validate_nullable_sep_by() {
+    let maybe_a = Rule::default();
+    maybe_a.maybe(Rule::default().literal("a"));
+
+    let maybe_comma = Rule::default();
+    maybe_comma.maybe(Rule::default().literal(","));
+
+    let r: Rule<i32> = Rule::default();
+    r.set_name("items").sep_by(0, u64::MAX, &maybe_a, &maybe_comma);
+
+    let ok: Rule<i32> = Rule::default();
+    ok.sep_by(0, u64::MAX, &maybe_a, Rule::default().literal(","));
+
+    assert_eq!(r.validate(), vec![GrammarIssue::NullableRepetition(String::from("items"))]);
+    assert!(ok.validate().is_empty());
+}
+
+#[test]
+fn validate_unreachable_alternative() {
+    let a = Rule::default();
+    a.literal("a");
+
+    let ab = Rule::default();
+    ab.literal("a").one(Rule::default().literal("b"));
+
+    let b = Rule::default();
+    b.literal("b");
+
+    let r: Rule<i32> = Rule::default();
+    r.set_name("r").any_of(vec![&a, &b, &ab]);
+
+    // The longer alternative goes first to be reachable.
+    let ok: Rule<i32> = Rule::default();
+    ok.any_of(vec![&ab, &a, &b]);
+
+    assert_eq!(r.validate(), vec![GrammarIssue::UnreachableAlternative { rule: String::from("r"), alternative: 2, shadowed_by: 0 }]);
+    assert!(ok.validate().is_empty());
+}
+
+#[test]
+fn validate_nullable_alternative() {
+    let r: Rule<i32> = Rule::default();
+    r.set_name("r").any_of(vec![Rule::default().maybe(Rule::default().literal("a")), Rule::default().literal("b")]);
+
+    assert_eq!(r.validate(), vec![GrammarIssue::UnreachableAlternative { rule: String::from("r"), alternative: 1, shadowed_by: 0 }]);
+
+    // These match without consuming input too, but they can fail so the next alternative is tried.
+    let kw = Rule::default();
+    kw.literal("if");
+
+    let alts: Vec<Rule<i32>> = vec![
+        Rule::default().eof().clone(),
+        Rule::default().not(&kw).literal("x").clone(),
+        Rule::default().not(&kw).clone(),
+        Rule::default().guard(|_, _| false).clone(),
+    ];
+
+    for alt in alts {
+        let r: Rule<i32> = Rule::default();
+        r.any_of(vec![&alt, Rule::default().literal("x"), &kw]);
+
+        assert!(r.validate().is_empty());
+        assert!(r.scan("if").is_ok());
+    }
+
+    let r: Rule<i32> = Rule::default();
+    r.any_of(vec![Rule::default().eof(), Rule::default().literal("x")]);
+    assert!(r.scan("x").is_ok());
+}
+
+#[test]
+fn validate_left_recursion() {
+    let expr: Rule<i32> = Rule::default();
+    expr.set_name("expr");
+
+    let num = Rule::default();
+    num.char_in('0', '9');
+
+    let plus = Rule::default();
+    plus.set_name("plus").one(&expr).literal("+").one(&num);
+
+    expr.any_of(vec![&plus, &num]);
+
+    let r: Rule<i32> = Rule::default();
+    r.set_name("root").one(&expr).eof();
+
+    // The cycle between expr and plus is reported once.
+    assert_eq!(r.validate(), vec![GrammarIssue::LeftRecursion(String::from("expr"))]);
+}
+
+#[test]
+fn validate_left_recursion_nullable_prefix() {
+    let r: Rule<i32> = Rule::default();
+    r.set_name("r").maybe(Rule::default().literal("-")).one(&r);
+
+    let ok: Rule<i32> = Rule::default();
+    ok.literal("-").maybe(&ok);
+
+    assert_eq!(r.validate(), vec![GrammarIssue::LeftRecursion(String::from("r"))]);
+    assert!(ok.validate().is_empty());
+}
+
+#[test]
+fn validate_display() {
+    let r: Rule<i32> = Rule::default();
+    r.literal("x").none_or_many(Rule::default().maybe(Rule::default().literal("y")));
+
+    let issues = r.validate();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].to_string(), "Repeated rule can match nothing in: \"x\" (\"y\"?)*");
+}